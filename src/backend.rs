@@ -0,0 +1,549 @@
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Network, Transaction, Txid};
+use bdk::blockchain::compact_filters::{BitcoinPeerConfig, CompactFiltersBlockchainConfig};
+use bdk::blockchain::esplora::EsploraBlockchainConfig;
+use bdk::blockchain::{
+    AnyBlockchain, AnyBlockchainConfig, Blockchain, Capability, ConfigurableBlockchain,
+    ElectrumBlockchain, Progress,
+};
+use bdk::database::{BatchDatabase, MemoryDatabase};
+use bdk::electrum_client::{Client, ConfigBuilder, Socks5Config};
+use bdk::{FeeRate, Wallet};
+use bitcoincore_rpc::Auth;
+use clap::Clap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::config::{Config, Settings, PROFILE_ENV};
+use crate::errors::SweepError;
+use crate::esplora::ProxiedEsplora;
+use crate::multi::MultiBlockchain;
+use crate::offline::{OfflineBlockchain, UtxoSet};
+use crate::rpc::RpcBlockchain;
+
+/// Network of a `-n` option, the same for every command: mainnet, testnet or regtest. Regtest
+/// keys are encoded as testnet ones, but its addresses differ
+pub fn parse_network(name: &str) -> Network {
+    match name {
+        "mainnet" => Network::Bitcoin,
+        "testnet" => Network::Testnet,
+        _ => Network::Regtest,
+    }
+}
+
+/// Blockchain server options shared by the commands that sync a wallet
+#[derive(Clap, Debug)]
+pub struct BackendOpt {
+    /// Profile of the config file to take the settings from. Options on the command line
+    /// override them
+    #[clap(long)]
+    profile: Option<String>,
+    /// Bitcoin network [default: testnet]
+    #[clap(short, possible_values = NETWORKS)]
+    network: Option<String>,
+    /// By default electrum server is used ssl://electrum.blockstream.info:60002 to query blockchain.
+    /// But you can override it with an esplora server of your choice
+    /// Examples: https://blockstream.info/testnet/api for testnet and https://blockstream.info/api for mainnet
+    /// Can be repeated, and combined with --server
+    #[clap(short = 'p', long)]
+    esplora: Vec<String>,
+    /// Electrum server to query the blockchain. Default="ssl://electrum.blockstream.info:60002"
+    /// In regtest mode the default is 127.0.0.1:51401. Can be repeated: the servers are tried in
+    /// order on errors
+    #[clap(long)]
+    server: Vec<String>,
+    /// Number of the servers (--server and --esplora) which have to return the same UTXOs. All
+    /// of them are queried in parallel, and the fee rate is the median of their estimates
+    /// [default: 1]
+    #[clap(long)]
+    quorum: Option<usize>,
+    /// You can pass a proxy e.g. localhost:9050 and then pass an onion address of an Electrum server
+    /// to the server arg, e.g.
+    /// explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion:143 for testnet
+    /// Esplora servers are queried through it too, e.g.
+    /// http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/testnet/api
+    #[clap(long)]
+    proxy: Option<String>,
+    /// Credentials of the SOCKS5 proxy as user:password. Tor builds a separate circuit for
+    /// every different credentials, so the queries of a sweep aren't linked to other traffic
+    #[clap(long, requires = "proxy")]
+    proxy_auth: Option<String>,
+    /// Address gap limit to search within for available funds [default: 20]
+    #[clap(short = 'g')]
+    address_gap_limit: Option<u32>,
+    /// Seconds the queries of the blockchain may take in total, after which the command fails
+    /// instead of waiting forever e.g. for a server over Tor. Electrum servers not behind
+    /// --proxy also get it as the timeout of each request, of up to 255 seconds
+    #[clap(long)]
+    timeout: Option<u64>,
+    /// Number of times a failed request to an Electrum server is retried [default: 2]
+    #[clap(long)]
+    retry: Option<u8>,
+    /// Number of parallel requests to an Esplora server [default: 4]
+    #[clap(long)]
+    concurrency: Option<u8>,
+    /// Check that the certificate of an ssl:// Electrum server is valid for its domain. Pass
+    /// false for a server with a self-signed certificate [default: true]
+    #[clap(long, parse(try_from_str), possible_values = &["true", "false"])]
+    validate_domain: Option<bool>,
+    /// Bitcoin Core RPC server of your own node to query instead of an Electrum or Esplora
+    /// server, e.g. http://127.0.0.1:8332 for mainnet. UTXOs are found with scantxoutset
+    #[clap(long, conflicts_with_all = &["esplora", "server", "proxy"])]
+    rpc: Option<String>,
+    /// Cookie file of the RPC server, e.g. ~/.bitcoin/.cookie
+    #[clap(long, requires = "rpc", conflicts_with = "rpc-user")]
+    rpc_cookie: Option<PathBuf>,
+    /// User of the RPC server (rpcuser)
+    #[clap(long, requires_all = &["rpc", "rpc-password"])]
+    rpc_user: Option<String>,
+    /// Password of the RPC server (rpcpassword)
+    #[clap(long, requires = "rpc-user")]
+    rpc_password: Option<String>,
+    /// Watch-only wallet of the node to sync through instead of scantxoutset, created if
+    /// missing. It finds unconfirmed transactions too, as bump-fee and cpfp need
+    #[clap(long, requires = "rpc")]
+    rpc_wallet: Option<String>,
+    /// Bitcoin node serving compact block filters (BIP157) to sync from instead of a server,
+    /// e.g. 127.0.0.1:8333 for mainnet. Only the blocks matching the filters of the descriptors
+    /// are downloaded. Can be repeated, and goes through --proxy if given. Peers don't estimate
+    /// fees, so --fee-rate is required outside of regtest
+    #[clap(long, conflicts_with_all = &["esplora", "server", "rpc"])]
+    peer: Vec<String>,
+    /// Directory storing the headers and filters downloaded from the peers, so a later sync only
+    /// downloads the new ones. Defaults to sweeptool-cbf/<network> in the temporary directory
+    #[clap(long, requires = "peer")]
+    cbf_dir: Option<PathBuf>,
+    /// Number of blocks from the genesis to skip when syncing from the peers, e.g. the height
+    /// the descriptors were created at
+    #[clap(long, requires = "peer")]
+    skip_blocks: Option<usize>,
+    /// -t of the config file, for the commands estimating a fee rate
+    #[clap(skip)]
+    target: Option<usize>,
+}
+
+const NETWORKS: &[&str] = &["mainnet", "testnet", "regtest"];
+
+impl BackendOpt {
+    /// Fill the options not on the command line from the SWEEPTOOL_* environment variables,
+    /// or else from the profile or the rest of the config file
+    pub fn configure(&mut self, config: Config) -> Result<(), SweepError> {
+        let profile = self
+            .profile
+            .clone()
+            .or_else(|| std::env::var(PROFILE_ENV).ok());
+        let settings = Settings::from_env()?.or(config.settings(profile.as_deref())?);
+
+        if let Some(ref network) = settings.network {
+            if !NETWORKS.contains(&network.as_str()) {
+                return Err(SweepError::Cli(format!(
+                    "invalid network {}, one of {} is expected",
+                    network,
+                    NETWORKS.join(", ")
+                )));
+            }
+        }
+        self.network = self.network.take().or(settings.network);
+        // servers on the command line, or another backend, replace all of those configured
+        if self.server.is_empty()
+            && self.esplora.is_empty()
+            && self.rpc.is_none()
+            && self.peer.is_empty()
+        {
+            self.server = settings.server.unwrap_or_default();
+            self.esplora = settings.esplora.unwrap_or_default();
+        }
+        self.quorum = self.quorum.or(settings.quorum);
+        // the RPC client has no SOCKS5 support, the node would be reached without the proxy
+        if let (Some(rpc), Some(proxy)) = (&self.rpc, &settings.proxy) {
+            return Err(SweepError::Cli(format!(
+                "--rpc {} can't go through the configured proxy {}, use a profile without a proxy",
+                rpc, proxy
+            )));
+        }
+        self.proxy = self.proxy.take().or(settings.proxy);
+        self.proxy_auth = self.proxy_auth.take().or(settings.proxy_auth);
+        self.address_gap_limit = self.address_gap_limit.or(settings.stop_gap);
+        self.timeout = self.timeout.or(settings.timeout);
+        self.retry = self.retry.or(settings.retry);
+        self.concurrency = self.concurrency.or(settings.concurrency);
+        self.validate_domain = self.validate_domain.or(settings.validate_domain);
+        self.target = settings.target;
+        Ok(())
+    }
+
+    pub fn stop_gap(&self) -> u32 {
+        self.address_gap_limit.unwrap_or(20)
+    }
+
+    /// Target of -t, or else of the configuration
+    pub fn target(&self, target: Option<usize>) -> usize {
+        target.or(self.target).unwrap_or(6)
+    }
+
+    /// Fee rate estimated by `backend` for -t. Peers estimate none, nor does a node without
+    /// enough data, which is an error outside of regtest rather than a sweep at the minimum
+    /// relay fee
+    pub fn fee_rate(
+        &self,
+        backend: &Backend,
+        target: Option<usize>,
+    ) -> Result<FeeRate, SweepError> {
+        if !self.peer.is_empty() && self.network() != Network::Regtest {
+            return Err(SweepError::Fee(
+                "peers don't estimate fees, pass --fee-rate".to_string(),
+            ));
+        }
+        match backend {
+            Backend::Rpc(rpc) => rpc.fee_rate(self.target(target)),
+            _ => Ok(backend.estimate_fee(self.target(target))?),
+        }
+    }
+
+    fn network_name(&self) -> &str {
+        self.network.as_deref().unwrap_or("testnet")
+    }
+
+    /// Fail the command with a network error once the timeout elapses, unless the deadline
+    /// returned is dropped before
+    pub fn deadline(&self) -> Option<Deadline> {
+        self.timeout.map(Deadline::start)
+    }
+
+    pub fn network(&self) -> Network {
+        parse_network(self.network_name())
+    }
+
+    /// Wallet of a descriptor pair connected to the blockchain server
+    pub fn wallet(
+        &self,
+        descriptor: &str,
+        descriptor_chg: &str,
+    ) -> Result<Wallet<Backend, MemoryDatabase>, SweepError> {
+        Ok(Wallet::new(
+            descriptor,
+            Some(descriptor_chg),
+            self.network(),
+            MemoryDatabase::default(),
+            self.backend()?,
+        )?)
+    }
+
+    /// Wallet of a descriptor pair with the UTXOs of `utxos`, without connecting to a server
+    pub fn offline_wallet(
+        &self,
+        descriptor: &str,
+        descriptor_chg: &str,
+        utxos: &UtxoSet,
+    ) -> Result<Wallet<Backend, MemoryDatabase>, SweepError> {
+        Ok(Wallet::new(
+            descriptor,
+            Some(descriptor_chg),
+            self.network(),
+            MemoryDatabase::default(),
+            Backend::Offline(OfflineBlockchain::new(utxos, self.network())?),
+        )?)
+    }
+
+    fn backend(&self) -> Result<Backend, SweepError> {
+        if let Some(ref url) = self.rpc {
+            let auth = match (&self.rpc_cookie, &self.rpc_user, &self.rpc_password) {
+                (Some(cookie), _, _) => Auth::CookieFile(cookie.clone()),
+                (None, Some(user), Some(password)) => {
+                    Auth::UserPass(user.clone(), password.clone())
+                }
+                _ => Auth::None,
+            };
+            return Ok(Backend::Rpc(RpcBlockchain::new(
+                url,
+                auth,
+                self.rpc_wallet.clone(),
+                self.network(),
+            )?));
+        }
+
+        let config_cbf = if self.peer.is_empty() {
+            None
+        } else {
+            let storage_dir = self.cbf_dir.clone().unwrap_or_else(|| {
+                std::env::temp_dir()
+                    .join("sweeptool-cbf")
+                    .join(self.network_name())
+            });
+            std::fs::create_dir_all(&storage_dir).map_err(|e| {
+                SweepError::Cli(format!("unable to create {}: {}", storage_dir.display(), e))
+            })?;
+            let credentials = self.proxy_credentials()?;
+            Some(AnyBlockchainConfig::CompactFilters(
+                CompactFiltersBlockchainConfig {
+                    peers: self
+                        .peer
+                        .iter()
+                        .map(|address| BitcoinPeerConfig {
+                            address: address.clone(),
+                            socks5: self.proxy.clone(),
+                            socks5_credentials: credentials.clone(),
+                        })
+                        .collect(),
+                    network: self.network(),
+                    storage_dir: storage_dir.to_string_lossy().to_string(),
+                    skip_blocks: self.skip_blocks,
+                },
+            ))
+        };
+
+        if let Some(config) = config_cbf {
+            return Ok(Backend::Any(AnyBlockchain::from_config(&config)?));
+        }
+
+        let mut servers = self.server.clone();
+        if servers.is_empty() && self.esplora.is_empty() {
+            servers.push(if self.network_name() == "regtest" {
+                "127.0.0.1:51401".to_string()
+            } else {
+                "ssl://electrum.blockstream.info:60002".to_string()
+            });
+        }
+        let quorum = self.quorum.unwrap_or(1);
+        let count = servers.len() + self.esplora.len();
+        if quorum == 0 || quorum > count {
+            return Err(SweepError::Cli(format!(
+                "quorum {} out of {} servers",
+                quorum, count
+            )));
+        }
+
+        // Electrum servers are connected to here. One failing is left out, like a server
+        // failing later, as long as the quorum can be reached
+        let mut backends = Vec::new();
+        let mut errors = Vec::new();
+        for url in servers {
+            match self.electrum(&url) {
+                Ok(backend) => backends.push((url, Backend::Any(backend))),
+                Err(e) => errors.push(e),
+            }
+        }
+        let proxy = self.proxy_url()?;
+        let concurrency = self.concurrency.unwrap_or(4);
+        for url in &self.esplora {
+            let backend = match proxy {
+                Some(ref proxy) => {
+                    ProxiedEsplora::new(url, proxy, concurrency).map(Backend::Esplora)
+                }
+                None => {
+                    let config = AnyBlockchainConfig::Esplora(EsploraBlockchainConfig {
+                        base_url: url.clone(),
+                        concurrency: Some(concurrency),
+                    });
+                    AnyBlockchain::from_config(&config)
+                        .map(Backend::Any)
+                        .map_err(SweepError::from)
+                }
+            };
+            match backend {
+                Ok(backend) => backends.push((url.clone(), backend)),
+                Err(e) => errors.push(e),
+            }
+        }
+        if backends.len() < quorum {
+            return Err(errors
+                .pop()
+                .unwrap_or_else(|| SweepError::Network("no server to query".to_string())));
+        }
+        if backends.len() == 1 {
+            return Ok(backends.remove(0).1);
+        }
+        Ok(Backend::Multi(MultiBlockchain::new(backends, quorum)))
+    }
+
+    /// User and password of --proxy-auth
+    fn proxy_credentials(&self) -> Result<Option<(String, String)>, SweepError> {
+        let auth = match self.proxy_auth {
+            Some(ref auth) => auth,
+            None => return Ok(None),
+        };
+        let mut parts = auth.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(user), Some(password)) => Ok(Some((user.to_string(), password.to_string()))),
+            _ => Err(SweepError::Cli(
+                "--proxy-auth has to be user:password".to_string(),
+            )),
+        }
+    }
+
+    fn socks5(&self) -> Result<Option<Socks5Config>, SweepError> {
+        let proxy = match self.proxy {
+            Some(ref proxy) => proxy,
+            None => return Ok(None),
+        };
+        Ok(Some(match self.proxy_credentials()? {
+            Some((user, password)) => Socks5Config::with_credentials(proxy, user, password),
+            None => Socks5Config::new(proxy),
+        }))
+    }
+
+    /// --proxy as the URL of a SOCKS5 proxy resolving the host names, as onion addresses can't
+    /// be resolved locally
+    fn proxy_url(&self) -> Result<Option<String>, SweepError> {
+        let proxy = match self.proxy {
+            Some(ref proxy) => proxy,
+            None => return Ok(None),
+        };
+        let mut url = reqwest::Url::parse(&format!("socks5h://{}", proxy))
+            .map_err(|e| SweepError::Cli(format!("invalid proxy {}: {}", proxy, e)))?;
+        if let Some((user, password)) = self.proxy_credentials()? {
+            // percent-encoded by the setters
+            url.set_username(&user)
+                .and_then(|_| url.set_password(Some(&password)))
+                .map_err(|_| SweepError::Cli(format!("invalid proxy {}", proxy)))?;
+        }
+        Ok(Some(url.to_string()))
+    }
+
+    /// Electrum server connected to with the client, as bdk's config has no domain validation
+    fn electrum(&self, url: &str) -> Result<AnyBlockchain, SweepError> {
+        // electrum-client has no timeout over a proxy, the deadline covers it
+        let timeout = match self.proxy {
+            Some(_) => None,
+            None => self.timeout.map(|t| t.min(u8::MAX as u64) as u8),
+        };
+        let config = ConfigBuilder::new()
+            .retry(self.retry.unwrap_or(2))
+            .timeout(timeout)?
+            .socks5(self.socks5()?)?
+            .validate_domain(self.validate_domain.unwrap_or(true))
+            .build();
+        let client = Client::from_config(url, config)?;
+        Ok(AnyBlockchain::Electrum(ElectrumBlockchain::from(client)))
+    }
+}
+
+/// Watchdog of `--timeout`. The queries of bdk can't be interrupted, so it exits the process
+pub struct Deadline {
+    _cancel: mpsc::Sender<()>,
+}
+
+impl Deadline {
+    fn start(seconds: u64) -> Self {
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            // a disconnection is the deadline dropped in time
+            if let Err(RecvTimeoutError::Timeout) =
+                cancelled.recv_timeout(Duration::from_secs(seconds))
+            {
+                crate::exit_with(
+                    SweepError::Network(format!(
+                        "no answer from the blockchain backend within the timeout of {} s",
+                        seconds
+                    )),
+                    crate::json_errors(),
+                );
+            }
+        });
+        Deadline { _cancel: cancel }
+    }
+}
+
+/// Blockchain backend of a wallet: one of the backends bdk supports, an Esplora server behind
+/// a proxy, several servers, a Bitcoin Core node, or the UTXOs of an offline sweep
+pub enum Backend {
+    Any(AnyBlockchain),
+    Esplora(ProxiedEsplora),
+    Rpc(RpcBlockchain),
+    Offline(OfflineBlockchain),
+    Multi(MultiBlockchain),
+}
+
+impl Backend {
+    /// Complete the inputs of a PSBT with what the backend knows beyond the wallet
+    pub fn complete_psbt(&self, psbt: &mut PartiallySignedTransaction) {
+        if let Backend::Offline(offline) = self {
+            offline.complete_psbt(psbt);
+        }
+    }
+}
+
+impl Blockchain for Backend {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        match self {
+            Backend::Any(any) => any.get_capabilities(),
+            Backend::Esplora(esplora) => esplora.get_capabilities(),
+            Backend::Rpc(rpc) => rpc.get_capabilities(),
+            Backend::Offline(offline) => offline.get_capabilities(),
+            Backend::Multi(multi) => multi.get_capabilities(),
+        }
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), bdk::Error> {
+        match self {
+            Backend::Any(any) => any.setup(stop_gap, database, progress_update),
+            Backend::Esplora(esplora) => esplora.setup(stop_gap, database, progress_update),
+            Backend::Rpc(rpc) => rpc.setup(stop_gap, database, progress_update),
+            Backend::Offline(offline) => offline.setup(stop_gap, database, progress_update),
+            Backend::Multi(multi) => multi.setup(stop_gap, database, progress_update),
+        }
+    }
+
+    fn sync<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        progress_update: P,
+    ) -> Result<(), bdk::Error> {
+        match self {
+            Backend::Any(any) => any.sync(stop_gap, database, progress_update),
+            Backend::Esplora(esplora) => esplora.sync(stop_gap, database, progress_update),
+            Backend::Rpc(rpc) => rpc.sync(stop_gap, database, progress_update),
+            Backend::Offline(offline) => offline.sync(stop_gap, database, progress_update),
+            Backend::Multi(multi) => multi.sync(stop_gap, database, progress_update),
+        }
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, bdk::Error> {
+        match self {
+            Backend::Any(any) => any.get_tx(txid),
+            Backend::Esplora(esplora) => esplora.get_tx(txid),
+            Backend::Rpc(rpc) => rpc.get_tx(txid),
+            Backend::Offline(offline) => offline.get_tx(txid),
+            Backend::Multi(multi) => multi.get_tx(txid),
+        }
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), bdk::Error> {
+        match self {
+            Backend::Any(any) => any.broadcast(tx),
+            Backend::Esplora(esplora) => esplora.broadcast(tx),
+            Backend::Rpc(rpc) => rpc.broadcast(tx),
+            Backend::Offline(offline) => offline.broadcast(tx),
+            Backend::Multi(multi) => multi.broadcast(tx),
+        }
+    }
+
+    fn get_height(&self) -> Result<u32, bdk::Error> {
+        match self {
+            Backend::Any(any) => any.get_height(),
+            Backend::Esplora(esplora) => esplora.get_height(),
+            Backend::Rpc(rpc) => rpc.get_height(),
+            Backend::Offline(offline) => offline.get_height(),
+            Backend::Multi(multi) => multi.get_height(),
+        }
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, bdk::Error> {
+        match self {
+            Backend::Any(any) => any.estimate_fee(target),
+            Backend::Esplora(esplora) => esplora.estimate_fee(target),
+            Backend::Rpc(rpc) => rpc.estimate_fee(target),
+            Backend::Offline(offline) => offline.estimate_fee(target),
+            Backend::Multi(multi) => multi.estimate_fee(target),
+        }
+    }
+}
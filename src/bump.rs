@@ -0,0 +1,162 @@
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::Transaction;
+use bdk::FeeRate;
+
+use crate::errors::SweepError;
+
+/// Minimum fee rate increase of a replacement (BIP125 rule 4), as Bitcoin Core's default
+/// incremental relay fee [sat/vB]
+const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// Bitcoin Core's default minimum relay fee [sat/vB]
+const MIN_RELAY_FEE: u64 = 1;
+
+/// Outputs below this value aren't relayed
+const DUST_LIMIT: u64 = 546;
+
+/// Whether `tx` signals replaceability (BIP125): an input with a sequence below 0xFFFFFFFE,
+/// which the relative timelocks of `older()` are as well
+pub fn signals_replaceability(tx: &Transaction) -> bool {
+    tx.input.iter().any(|input| input.sequence < 0xFFFF_FFFE)
+}
+
+/// Fee of a replacement of a transaction of `vsize` vbytes paying `old_fee`. It pays at least
+/// the original fee plus its own relay at the incremental relay fee (BIP125 rules 3 and 4).
+/// A `requested` fee rate below that is an error, an `estimate` is raised to it.
+pub fn replacement_fee(
+    old_fee: u64,
+    vsize: u64,
+    requested: Option<f32>,
+    estimate: FeeRate,
+) -> Result<u64, SweepError> {
+    let min_fee = old_fee + vsize * INCREMENTAL_RELAY_FEE;
+    match requested {
+        Some(rate) => {
+            let fee = (rate * vsize as f32).ceil() as u64;
+            if fee < min_fee {
+                return Err(SweepError::Fee(format!(
+                    "fee rate {} sat/vB is below {:.2} sat/vB, the minimum of a replacement paying {} sats",
+                    rate,
+                    min_fee as f32 / vsize as f32,
+                    min_fee
+                )));
+            }
+            Ok(fee)
+        }
+        None => {
+            let fee = (estimate.as_sat_vb() * vsize as f32).ceil() as u64;
+            Ok(fee.max(min_fee))
+        }
+    }
+}
+
+/// Fee of a child of `child_vsize` vbytes lifting the package with its parent to `rate`
+/// [sat/vB] (CPFP). The child pays at least the minimum relay fee for its own size.
+pub fn child_fee(
+    parent_fee: u64,
+    parent_vsize: u64,
+    child_vsize: u64,
+    rate: f32,
+) -> Result<u64, SweepError> {
+    let parent_rate = parent_fee as f32 / parent_vsize as f32;
+    if parent_rate >= rate {
+        return Err(SweepError::Fee(format!(
+            "the sweep already pays {:.2} sat/vB, not less than {} sat/vB",
+            parent_rate, rate
+        )));
+    }
+    let package_fee = (rate * (parent_vsize + child_vsize) as f32).ceil() as u64;
+    Ok(package_fee
+        .saturating_sub(parent_fee)
+        .max(child_vsize * MIN_RELAY_FEE))
+}
+
+/// Take `extra` fees from the outputs of `psbt` in equal parts, rounded up like the
+/// `fee_per_utxo` of a sweep. The outputs keep their indexes, one that can't pay its share
+/// without falling below the dust limit is an error. Returns the extra fees actually taken, a
+/// multiple of the number of outputs.
+pub fn redistribute_fee(
+    psbt: &mut PartiallySignedTransaction,
+    extra: u64,
+) -> Result<u64, SweepError> {
+    let outputs = &mut psbt.global.unsigned_tx.output;
+    if outputs.is_empty() {
+        return Err(SweepError::Psbt(
+            "the transaction has no outputs".to_string(),
+        ));
+    }
+    let per_output = (extra + outputs.len() as u64 - 1) / outputs.len() as u64;
+    for (i, output) in outputs.iter_mut().enumerate() {
+        let value = output.value;
+        output.value = value
+            .checked_sub(per_output)
+            .filter(|value| *value >= DUST_LIMIT)
+            .ok_or_else(|| {
+                SweepError::InsufficientFunds(format!(
+                    "output {} of {} sats can't pay {} sats more fees",
+                    i, value, per_output
+                ))
+            })?;
+    }
+    Ok(per_output * outputs.len() as u64)
+}
+
+/// Drop the signatures of the original, which don't sign the replacement
+pub fn clear_signatures(psbt: &mut PartiallySignedTransaction) {
+    for input in psbt.inputs.iter_mut() {
+        input.partial_sigs.clear();
+        input.final_script_sig = None;
+        input.final_script_witness = None;
+    }
+}
+
+#[test]
+fn replacement_fee_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::Script;
+    use bdk::bitcoin::TxOut;
+
+    let estimate = FeeRate::from_sat_per_vb(5.0);
+    // original of 200 vbytes at 2 sat/vB
+    assert_eq!(replacement_fee(400, 200, Some(4.0), estimate)?, 800);
+    // at least 1 sat/vB more
+    assert!(replacement_fee(400, 200, Some(2.5), estimate).is_err());
+    assert_eq!(replacement_fee(400, 200, Some(3.0), estimate)?, 600);
+    assert_eq!(replacement_fee(400, 200, None, estimate)?, 1000);
+    assert_eq!(
+        replacement_fee(400, 200, None, FeeRate::from_sat_per_vb(1.0))?,
+        600
+    );
+
+    let output = |value| TxOut {
+        value,
+        script_pubkey: Script::new(),
+    };
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![],
+        output: vec![output(10_000), output(20_000), output(30_000)],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    assert_eq!(redistribute_fee(&mut psbt, 1000)?, 1002);
+    let values: Vec<u64> = psbt
+        .global
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|o| o.value)
+        .collect();
+    assert_eq!(values, vec![9_666, 19_666, 29_666]);
+
+    // the first output would be dust
+    assert!(redistribute_fee(&mut psbt, 3 * 9_200).is_err());
+
+    // parent of 200 vbytes at 1 sat/vB and a child of 100 vbytes lifted to 5 sat/vB
+    assert_eq!(child_fee(200, 200, 100, 5.0)?, 1300);
+    assert_eq!(child_fee(200, 200, 100, 1.5)?, 250);
+    // the child pays for its own relay at least
+    assert_eq!(child_fee(175, 200, 100, 0.9)?, 100);
+    assert!(child_fee(1000, 200, 100, 5.0).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::errors::SweepError;
+
+/// Environment variable with the path of the config file, instead of the default one
+pub const CONFIG_ENV: &str = "SWEEPTOOL_CONFIG";
+/// Environment variable selecting a profile, like --profile
+pub const PROFILE_ENV: &str = "SWEEPTOOL_PROFILE";
+
+/// Config file, in TOML: settings for every invocation, and named profiles of settings
+/// selected with --profile, e.g.
+///
+/// timeout = 300
+///
+/// [profile.mainnet-tor]
+/// network = "mainnet"
+/// server = ["explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion:110"]
+/// proxy = "127.0.0.1:9050"
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    defaults: Settings,
+    profiles: BTreeMap<String, Settings>,
+}
+
+/// Settings of the config file or of the SWEEPTOOL_* environment variables. Every one of them
+/// can be overridden on the command line
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    /// -n
+    pub network: Option<String>,
+    /// --server
+    pub server: Option<Vec<String>>,
+    /// --esplora
+    pub esplora: Option<Vec<String>>,
+    /// --quorum
+    pub quorum: Option<usize>,
+    /// --proxy
+    pub proxy: Option<String>,
+    /// --proxy-auth
+    pub proxy_auth: Option<String>,
+    /// --timeout [s]
+    pub timeout: Option<u64>,
+    /// --retry
+    pub retry: Option<u8>,
+    /// --concurrency
+    pub concurrency: Option<u8>,
+    /// --validate-domain
+    pub validate_domain: Option<bool>,
+    /// -g
+    pub stop_gap: Option<u32>,
+    /// -t
+    pub target: Option<usize>,
+}
+
+impl Config {
+    /// Config file of $SWEEPTOOL_CONFIG, or else sweeptool/config.toml in $XDG_CONFIG_HOME or
+    /// ~/.config. Only the former has to exist
+    pub fn load() -> Result<Self, SweepError> {
+        let path = match std::env::var_os(CONFIG_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let file = std::fs::read_to_string(&path)
+            .map_err(|e| SweepError::Cli(format!("unable to read {}: {}", path.display(), e)))?;
+        Config::parse(&file)
+            .map_err(|e| SweepError::Cli(format!("invalid config file {}: {}", path.display(), e)))
+    }
+
+    fn parse(file: &str) -> Result<Self, toml::de::Error> {
+        // the profiles are taken out, so the settings can be strict about unknown keys
+        let mut table: toml::value::Table = toml::from_str(file)?;
+        let profiles = match table.remove("profile") {
+            Some(profiles) => profiles.try_into()?,
+            None => BTreeMap::new(),
+        };
+        Ok(Config {
+            defaults: toml::Value::Table(table).try_into()?,
+            profiles,
+        })
+    }
+
+    /// Settings of a profile, falling back on those outside of the profiles
+    pub fn settings(self, profile: Option<&str>) -> Result<Settings, SweepError> {
+        let Config {
+            defaults,
+            mut profiles,
+        } = self;
+        let name = match profile {
+            Some(name) => name,
+            None => return Ok(defaults),
+        };
+        match profiles.remove(name) {
+            Some(settings) => Ok(settings.or(defaults)),
+            None => Err(SweepError::Cli(format!(
+                "no profile {} in the config file, only: {}",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ))),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings of the SWEEPTOOL_<SETTING> environment variables, e.g. SWEEPTOOL_STOP_GAP.
+    /// Lists are separated by commas
+    pub fn from_env() -> Result<Self, SweepError> {
+        Ok(Settings {
+            network: env("NETWORK")?,
+            server: env_list("SERVER"),
+            esplora: env_list("ESPLORA"),
+            quorum: env("QUORUM")?,
+            proxy: env("PROXY")?,
+            proxy_auth: env("PROXY_AUTH")?,
+            timeout: env("TIMEOUT")?,
+            retry: env("RETRY")?,
+            concurrency: env("CONCURRENCY")?,
+            validate_domain: env("VALIDATE_DOMAIN")?,
+            stop_gap: env("STOP_GAP")?,
+            target: env("TARGET")?,
+        })
+    }
+
+    /// These settings, falling back on `defaults` for those missing
+    pub fn or(self, defaults: Settings) -> Settings {
+        Settings {
+            network: self.network.or(defaults.network),
+            server: self.server.or(defaults.server),
+            esplora: self.esplora.or(defaults.esplora),
+            quorum: self.quorum.or(defaults.quorum),
+            proxy: self.proxy.or(defaults.proxy),
+            proxy_auth: self.proxy_auth.or(defaults.proxy_auth),
+            timeout: self.timeout.or(defaults.timeout),
+            retry: self.retry.or(defaults.retry),
+            concurrency: self.concurrency.or(defaults.concurrency),
+            validate_domain: self.validate_domain.or(defaults.validate_domain),
+            stop_gap: self.stop_gap.or(defaults.stop_gap),
+            target: self.target.or(defaults.target),
+        }
+    }
+}
+
+fn env<T: FromStr>(setting: &str) -> Result<Option<T>, SweepError>
+where
+    T::Err: std::fmt::Display,
+{
+    let var = format!("SWEEPTOOL_{}", setting);
+    match std::env::var(&var) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| SweepError::Cli(format!("invalid {}={}: {}", var, value, e))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_list(setting: &str) -> Option<Vec<String>> {
+    std::env::var(format!("SWEEPTOOL_{}", setting))
+        .ok()
+        .map(|list| {
+            list.split(',')
+                .map(|item| item.trim().to_string())
+                .collect()
+        })
+}
+
+fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("sweeptool").join("config.toml"))
+}
+
+#[test]
+fn config_test() {
+    let config = Config::parse("timeout = 300\nvalidate_domain = false\nstop_gap = 50").unwrap();
+    assert_eq!(
+        config.settings(None).unwrap(),
+        Settings {
+            timeout: Some(300),
+            validate_domain: Some(false),
+            stop_gap: Some(50),
+            ..Settings::default()
+        }
+    );
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+
+    // a typo isn't silently ignored
+    assert!(Config::parse("timout = 300").is_err());
+    assert!(Config::parse("retry = 1000").is_err());
+    assert!(Config::parse("[profile.ci]\ntimout = 300").is_err());
+}
+
+#[test]
+fn profile_test() {
+    let file = r#"
+        timeout = 300
+        network = "testnet"
+
+        [profile.mainnet-tor]
+        network = "mainnet"
+        server = ["explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion:110"]
+        proxy = "127.0.0.1:9050"
+
+        [profile.regtest-ci]
+        timeout = 30
+    "#;
+    let settings = Config::parse(file)
+        .unwrap()
+        .settings(Some("mainnet-tor"))
+        .unwrap();
+    assert_eq!(settings.network.as_deref(), Some("mainnet"));
+    assert_eq!(settings.proxy.as_deref(), Some("127.0.0.1:9050"));
+    assert_eq!(settings.timeout, Some(300));
+
+    let settings = Config::parse(file)
+        .unwrap()
+        .settings(Some("regtest-ci"))
+        .unwrap();
+    assert_eq!(settings.network.as_deref(), Some("testnet"));
+    assert_eq!(settings.timeout, Some(30));
+
+    assert!(Config::parse(file)
+        .unwrap()
+        .settings(Some("mainnet"))
+        .is_err());
+}
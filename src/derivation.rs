@@ -0,0 +1,199 @@
+use bdk::bitcoin::Script;
+use bdk::database::BatchDatabase;
+use bdk::wallet::AddressIndex;
+use bdk::{KeychainKind, Wallet};
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::SweepError;
+
+/// Script pubkeys of a descriptor and its change descriptor, mapped to their keychain and
+/// derivation index. Addresses are derived once, so looking up a UTXO doesn't cost any
+/// derivations.
+pub struct DerivationMap {
+    scripts: HashMap<Script, (KeychainKind, u32)>,
+    /// number of indexes derived for each keychain
+    derived: u32,
+}
+
+impl DerivationMap {
+    /// Derive the first `count` addresses of `external` and `internal`
+    pub fn new<B, D: BatchDatabase>(
+        external: &Wallet<B, D>,
+        internal: &Wallet<B, D>,
+        count: u32,
+    ) -> Result<Self, SweepError> {
+        let mut map = DerivationMap {
+            scripts: HashMap::new(),
+            derived: 0,
+        };
+        map.derive(external, internal, count)?;
+        Ok(map)
+    }
+
+    /// Keep deriving until all of `scripts` are found. Like the wallet sync, the search only
+    /// continues while one of the last `gap` indexes is in `used`, i.e. has a history, so
+    /// indexes beyond the initial gap limit are covered if the sync could discover them.
+    pub fn cover<B, D: BatchDatabase>(
+        &mut self,
+        external: &Wallet<B, D>,
+        internal: &Wallet<B, D>,
+        scripts: &[Script],
+        used: &HashSet<Script>,
+        gap: u32,
+    ) -> Result<(), SweepError> {
+        while !scripts.iter().all(|s| self.scripts.contains_key(s)) {
+            let last_used = self
+                .scripts
+                .iter()
+                .filter(|(script, _)| used.contains(script))
+                .map(|(_, (_, index))| *index)
+                .max();
+            let count = last_used.map_or(gap, |index| index + 1 + gap);
+            if count <= self.derived {
+                // the rest is not derived from the descriptors
+                break;
+            }
+            self.derive(external, internal, count)?;
+        }
+        Ok(())
+    }
+
+    fn derive<B, D: BatchDatabase>(
+        &mut self,
+        external: &Wallet<B, D>,
+        internal: &Wallet<B, D>,
+        count: u32,
+    ) -> Result<(), SweepError> {
+        for i in self.derived..count {
+            let address = external.get_address(AddressIndex::Peek(i))?;
+            self.scripts
+                .insert(address.address.script_pubkey(), (KeychainKind::External, i));
+            let address = internal.get_address(AddressIndex::Peek(i))?;
+            self.scripts
+                .insert(address.address.script_pubkey(), (KeychainKind::Internal, i));
+        }
+        self.derived = self.derived.max(count);
+        Ok(())
+    }
+
+    pub fn get(&self, script: &Script) -> Option<(KeychainKind, u32)> {
+        self.scripts.get(script).copied()
+    }
+}
+
+#[test]
+fn derivation_map_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::{Address, Network};
+    use bdk::database::MemoryDatabase;
+    use std::str::FromStr;
+
+    let external = Wallet::new_offline(
+        "pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/0/*)",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    let internal = Wallet::new_offline(
+        "pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/1/*)",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+
+    let map = DerivationMap::new(&external, &internal, 5)?;
+    let script = |address: &str| Address::from_str(address).unwrap().script_pubkey();
+
+    assert_eq!(
+        map.get(&script("mvCntejWFwemnhSsCU51s7UKHqV37jn41V")),
+        Some((KeychainKind::External, 3))
+    );
+    assert_eq!(
+        map.get(&script("mrqSutMAGBAont2XR3NY56VoY9QQRUAM2n")),
+        Some((KeychainKind::Internal, 2))
+    );
+    // beyond the gap limit, found through a chain of used addresses
+    let used: HashSet<Script> = [3, 8, 12]
+        .iter()
+        .map(|i| {
+            external
+                .get_address(AddressIndex::Peek(*i))
+                .map(|a| a.address.script_pubkey())
+        })
+        .collect::<Result<_, _>>()?;
+    let beyond = external
+        .get_address(AddressIndex::Peek(12))?
+        .address
+        .script_pubkey();
+    assert_eq!(map.get(&beyond), None);
+
+    let mut covered = DerivationMap::new(&external, &internal, 5)?;
+    covered.cover(&external, &internal, &[beyond.clone()], &used, 5)?;
+    assert_eq!(covered.get(&beyond), Some((KeychainKind::External, 12)));
+
+    // without a history the search stops at the gap limit
+    let mut covered = DerivationMap::new(&external, &internal, 5)?;
+    covered.cover(&external, &internal, &[beyond.clone()], &HashSet::new(), 5)?;
+    assert_eq!(covered.get(&beyond), None);
+
+    // not derived from the descriptors
+    assert_eq!(
+        map.get(&script("2N3oefVeg6stiTb5Kh3ozCSkaqmx91FDbsm")),
+        None
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cover_past_gap_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::Network;
+    use bdk::database::MemoryDatabase;
+
+    let external = Wallet::new_offline(
+        "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*)",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    let internal = Wallet::new_offline(
+        "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/1/*)",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    fn script<B, D: BatchDatabase>(
+        wallet: &Wallet<B, D>,
+        index: u32,
+    ) -> Result<Script, SweepError> {
+        Ok(wallet
+            .get_address(AddressIndex::Peek(index))?
+            .address
+            .script_pubkey())
+    }
+
+    // a UTXO at index 57 with a gap limit of 20, found by the sync because 15, 30 and 45 of
+    // the external keychain and 10 of the internal one have a history
+    let used: HashSet<Script> = vec![
+        script(&external, 15)?,
+        script(&external, 30)?,
+        script(&external, 45)?,
+        script(&external, 57)?,
+        script(&internal, 10)?,
+    ]
+    .into_iter()
+    .collect();
+    let utxos = vec![script(&external, 57)?, script(&internal, 10)?];
+
+    let mut map = DerivationMap::new(&external, &internal, 20)?;
+    assert_eq!(map.get(&utxos[0]), None);
+    map.cover(&external, &internal, &utxos, &used, 20)?;
+    assert_eq!(map.get(&utxos[0]), Some((KeychainKind::External, 57)));
+    assert_eq!(map.get(&utxos[1]), Some((KeychainKind::Internal, 10)));
+
+    // more than the gap limit past the last used index, the sync can't find it either
+    let far = script(&external, 80)?;
+    map.cover(&external, &internal, &[far.clone()], &used, 20)?;
+    assert_eq!(map.get(&far), None);
+
+    Ok(())
+}
@@ -1,80 +1,255 @@
 //extern crate bitcoin;
 extern crate hex;
 extern crate serde_json;
+use std::fmt;
 use ur_rs::bytewords;
 
+/// Hint of the errors about a spending path missing from a descriptor with several
+const POLICY_PATH_REQUIRED: &str =
+    "the descriptor has several spending paths, select one with --policy-path";
+
+/// Errors returned by sweeptool. Every variant has a stable numeric code and maps to a
+/// process exit code, so wrappers don't have to parse the message.
 #[derive(Debug)]
-pub struct SweepError {
-    kind: String,
-    message: String,
+pub enum SweepError {
+    /// Invalid combination of command line arguments
+    Cli(String),
+    /// Unable to reach the blockchain server
+    Network(String),
+    /// The blockchain backend or the wallet failed
+    Backend(String),
+    /// Invalid output descriptor or key
+    Descriptor(String),
+    /// Invalid address
+    Address(String),
+    /// Invalid or unsupported UR
+    Ur(String),
+    /// Invalid PSBT
+    Psbt(String),
+    /// An output doesn't have the script type of its inputs (--check-script-type)
+    ScriptType(String),
+    /// Fee estimation or calculation failed
+    Fee(String),
+    /// The funds don't cover the fees
+    InsufficientFunds(String),
+    /// A UTXO doesn't derive from the descriptor within the address gap limit
+    GapLimit(String),
+    /// No UTXOs found
+    NothingToSweep(String),
+    /// The transaction can't be replaced or accelerated: unknown, confirmed or not signaling RBF
+    NotReplaceable(String),
+    /// Unable to sign the PSBT
+    Signing(String),
+    /// The user didn't confirm the summary of --interactive
+    Aborted(String),
+    /// Anything else, e.g. serialization of the output
+    Internal(String),
 }
 
 impl SweepError {
-    pub fn new(kind: String, message: String) -> Self {
-        SweepError { kind, message }
+    /// Stable error code
+    pub fn code(&self) -> u32 {
+        match self {
+            SweepError::Internal(_) => 1,
+            SweepError::Cli(_) => 10,
+            SweepError::Network(_) => 20,
+            SweepError::Backend(_) => 21,
+            SweepError::Descriptor(_) => 30,
+            SweepError::Address(_) => 31,
+            SweepError::Ur(_) => 32,
+            SweepError::Psbt(_) => 33,
+            SweepError::ScriptType(_) => 34,
+            SweepError::Fee(_) => 40,
+            SweepError::InsufficientFunds(_) => 41,
+            SweepError::GapLimit(_) => 42,
+            SweepError::NothingToSweep(_) => 43,
+            SweepError::NotReplaceable(_) => 44,
+            SweepError::Signing(_) => 50,
+            SweepError::Aborted(_) => 60,
+        }
+    }
+
+    /// Stable name of the error kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SweepError::Internal(_) => "internal",
+            SweepError::Cli(_) => "cli",
+            SweepError::Network(_) => "network",
+            SweepError::Backend(_) => "backend",
+            SweepError::Descriptor(_) => "descriptor",
+            SweepError::Address(_) => "address",
+            SweepError::Ur(_) => "ur",
+            SweepError::Psbt(_) => "psbt",
+            SweepError::ScriptType(_) => "script_type",
+            SweepError::Fee(_) => "fee",
+            SweepError::InsufficientFunds(_) => "insufficient_funds",
+            SweepError::GapLimit(_) => "gap_limit",
+            SweepError::NothingToSweep(_) => "nothing_to_sweep",
+            SweepError::NotReplaceable(_) => "not_replaceable",
+            SweepError::Signing(_) => "signing",
+            SweepError::Aborted(_) => "aborted",
+        }
+    }
+
+    /// Process exit code. Usage errors reported by clap exit with 2 as well
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SweepError::Internal(_) => 1,
+            SweepError::Cli(_) => 2,
+            SweepError::Network(_) | SweepError::Backend(_) => 3,
+            SweepError::Descriptor(_)
+            | SweepError::Address(_)
+            | SweepError::Ur(_)
+            | SweepError::Psbt(_)
+            | SweepError::ScriptType(_) => 4,
+            SweepError::Fee(_)
+            | SweepError::InsufficientFunds(_)
+            | SweepError::GapLimit(_)
+            | SweepError::NotReplaceable(_) => 5,
+            SweepError::NothingToSweep(_) => 6,
+            SweepError::Signing(_) => 7,
+            SweepError::Aborted(_) => 8,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SweepError::Internal(m)
+            | SweepError::Cli(m)
+            | SweepError::Network(m)
+            | SweepError::Backend(m)
+            | SweepError::Descriptor(m)
+            | SweepError::Address(m)
+            | SweepError::Ur(m)
+            | SweepError::Psbt(m)
+            | SweepError::ScriptType(m)
+            | SweepError::Fee(m)
+            | SweepError::InsufficientFunds(m)
+            | SweepError::GapLimit(m)
+            | SweepError::NothingToSweep(m)
+            | SweepError::NotReplaceable(m)
+            | SweepError::Signing(m)
+            | SweepError::Aborted(m) => m,
+        }
+    }
+
+    /// Error as printed with `--json-errors`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "kind": self.kind(),
+                "message": self.message(),
+            }
+        })
     }
 }
 
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} error ({}): {}",
+            self.kind(),
+            self.code(),
+            self.message()
+        )
+    }
+}
+
+impl std::error::Error for SweepError {}
+
 impl From<bdk::bitcoin::util::key::Error> for SweepError {
     fn from(error: bdk::bitcoin::util::key::Error) -> Self {
-        SweepError {
-            kind: String::from("Address"),
-            message: error.to_string(),
-        }
+        SweepError::Descriptor(error.to_string())
     }
 }
 
 use bdk::bitcoin::util::address::Error as AddressError;
 impl From<AddressError> for SweepError {
     fn from(error: AddressError) -> Self {
-        SweepError {
-            kind: String::from("Address"),
-            message: error.to_string(),
-        }
+        SweepError::Address(error.to_string())
     }
 }
 
 impl From<serde_json::Error> for SweepError {
     fn from(error: serde_json::Error) -> Self {
-        SweepError {
-            kind: String::from("serde_json"),
-            message: error.to_string(),
-        }
+        SweepError::Internal(error.to_string())
     }
 }
 
 impl From<bytewords::Error> for SweepError {
     fn from(error: bytewords::Error) -> Self {
-        SweepError {
-            kind: String::from("cbor"),
-            message: error.to_string(),
-        }
+        SweepError::Ur(error.to_string())
     }
 }
 
 impl From<bdk::Error> for SweepError {
     fn from(error: bdk::Error) -> Self {
-        SweepError {
-            kind: String::from("bdk"),
-            message: error.to_string(),
+        let message = error.to_string();
+        match error {
+            bdk::Error::InsufficientFunds { .. } => SweepError::InsufficientFunds(message),
+            bdk::Error::FeeRateTooLow { .. } | bdk::Error::FeeTooLow { .. } => {
+                SweepError::Fee(message)
+            }
+            bdk::Error::Electrum(_) | bdk::Error::Esplora(_) | bdk::Error::CompactFilters(_) => {
+                SweepError::Network(message)
+            }
+            bdk::Error::Descriptor(_)
+            | bdk::Error::Miniscript(_)
+            | bdk::Error::Key(_)
+            | bdk::Error::ChecksumMismatch => SweepError::Descriptor(message),
+            bdk::Error::SpendingPolicyRequired(_) => {
+                SweepError::Cli(format!("{}: {}", message, POLICY_PATH_REQUIRED))
+            }
+            bdk::Error::TransactionNotFound
+            | bdk::Error::TransactionConfirmed
+            | bdk::Error::IrreplaceableTransaction => SweepError::NotReplaceable(message),
+            bdk::Error::Signer(_) => SweepError::Signing(message),
+            bdk::Error::Psbt(_) | bdk::Error::PsbtParse(_) => SweepError::Psbt(message),
+            _ => SweepError::Backend(message),
+        }
+    }
+}
+
+impl From<bdk::descriptor::policy::PolicyError> for SweepError {
+    fn from(error: bdk::descriptor::policy::PolicyError) -> Self {
+        match error {
+            // a path through a threshold with several choices wasn't given
+            bdk::descriptor::policy::PolicyError::NotEnoughItemsSelected(_) => {
+                SweepError::Cli(format!("{}: {}", error, POLICY_PATH_REQUIRED))
+            }
+            _ => SweepError::Descriptor(error.to_string()),
         }
     }
 }
 
 impl From<bdk::electrum_client::Error> for SweepError {
     fn from(error: bdk::electrum_client::Error) -> Self {
-        SweepError {
-            kind: String::from("electrum client"),
-            message: error.to_string(),
-        }
+        SweepError::Network(error.to_string())
     }
 }
 
 impl From<serde_cbor::Error> for SweepError {
     fn from(error: serde_cbor::Error) -> Self {
-        SweepError {
-            kind: String::from("serde_cbor"),
-            message: error.to_string(),
-        }
+        SweepError::Ur(error.to_string())
+    }
+}
+
+impl From<bdk::bitcoin::util::bip32::Error> for SweepError {
+    fn from(error: bdk::bitcoin::util::bip32::Error) -> Self {
+        SweepError::Descriptor(error.to_string())
+    }
+}
+
+impl From<bdk::bitcoin::secp256k1::Error> for SweepError {
+    fn from(error: bdk::bitcoin::secp256k1::Error) -> Self {
+        SweepError::Descriptor(error.to_string())
+    }
+}
+
+impl From<bdk::bitcoin::consensus::encode::Error> for SweepError {
+    fn from(error: bdk::bitcoin::consensus::encode::Error) -> Self {
+        SweepError::Psbt(error.to_string())
     }
 }
@@ -0,0 +1,231 @@
+use bdk::bitcoin::consensus::{deserialize, serialize};
+use bdk::bitcoin::hashes::{sha256, Hash};
+use bdk::bitcoin::{Script, Transaction, Txid};
+use bdk::blockchain::{Blockchain, Capability, Progress};
+use bdk::database::BatchDatabase;
+use bdk::{Error, FeeRate};
+use reqwest::blocking::Client;
+use reqwest::{Proxy, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::errors::SweepError;
+use crate::rpc::{store, WalletTx};
+
+/// Confirmed transactions of a script listed by Esplora per page
+const PAGE_SIZE: usize = 25;
+
+/// Esplora server queried through a SOCKS5 proxy, which bdk's Esplora client can't be given.
+/// Like the RPC backend, it stores the history of every script the wallet caches at once.
+pub struct ProxiedEsplora {
+    url: String,
+    client: Client,
+    concurrency: usize,
+}
+
+#[derive(Deserialize)]
+struct EsploraTx {
+    txid: String,
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    confirmed: bool,
+    block_height: Option<u32>,
+    block_time: Option<u64>,
+}
+
+impl ProxiedEsplora {
+    /// Client of the Esplora server `url` connecting through `proxy`, a socks5h:// URL so the
+    /// host names are resolved by the proxy
+    pub fn new(url: &str, proxy: &str, concurrency: u8) -> Result<Self, SweepError> {
+        let proxy = Proxy::all(proxy)
+            .map_err(|e| SweepError::Cli(format!("invalid proxy {}: {}", proxy, e)))?;
+        let client = Client::builder()
+            .proxy(proxy)
+            // the deadline of --timeout covers the requests over the proxy
+            .timeout(None)
+            .build()
+            .map_err(|e| SweepError::Network(e.to_string()))?;
+        Ok(ProxiedEsplora {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+            concurrency: concurrency.max(1) as usize,
+        })
+    }
+
+    /// Body of the answer to a GET request, None if not found
+    fn get(&self, path: &str) -> Result<Option<String>, Error> {
+        let error = |e: reqwest::Error| Error::Generic(format!("{}{}: {}", self.url, path, e));
+        let response = self
+            .client
+            .get(&format!("{}{}", self.url, path))
+            .send()
+            .map_err(error)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response
+            .error_for_status()
+            .and_then(|response| response.text())
+            .map(Some)
+            .map_err(error)
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = self
+            .get(path)?
+            .ok_or_else(|| Error::Generic(format!("{}{} not found", self.url, path)))?;
+        serde_json::from_str(&body)
+            .map_err(|e| Error::Generic(format!("invalid answer of {}{}: {}", self.url, path, e)))
+    }
+
+    /// Transactions of a script: those in the mempool, then the confirmed ones page by page
+    fn history(&self, script: &Script) -> Result<Vec<EsploraTx>, Error> {
+        // the scripthash of Electrum, the hash reversed
+        let mut hash = sha256::Hash::hash(script.as_bytes()).into_inner();
+        hash.reverse();
+        let path = format!("/scripthash/{}/txs", hex::encode(hash));
+
+        let mut txs: Vec<EsploraTx> = self.get_json(&path)?;
+        let mut page = txs.iter().filter(|tx| tx.status.confirmed).count();
+        while page == PAGE_SIZE {
+            let last = &txs[txs.len() - 1].txid;
+            let next: Vec<EsploraTx> = self.get_json(&format!("{}/chain/{}", path, last))?;
+            page = next.len();
+            txs.extend(next);
+        }
+        Ok(txs)
+    }
+
+    fn raw_tx(&self, txid: &str) -> Result<Option<Transaction>, Error> {
+        match self.get(&format!("/tx/{}/hex", txid))? {
+            Some(hex) => {
+                let bytes = hex::decode(hex.trim())
+                    .map_err(|e| Error::Generic(format!("invalid transaction: {}", e)))?;
+                Ok(Some(deserialize(&bytes)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Answers to `query` for every item, with up to `concurrency` requests at a time
+    fn query_all<T, R, F>(&self, items: &[T], query: F) -> Result<Vec<R>, Error>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> Result<R, Error> + Sync,
+    {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+        let size = (items.len() + self.concurrency - 1) / self.concurrency;
+        let results: Vec<Result<Vec<R>, String>> = crossbeam_utils::thread::scope(|scope| {
+            let handles: Vec<_> = items
+                .chunks(size)
+                .map(|chunk| {
+                    let query = &query;
+                    scope.spawn(move |_| {
+                        chunk
+                            .iter()
+                            .map(|item| query(item).map_err(|e| e.to_string()))
+                            .collect()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("query panicked".to_string()))
+                })
+                .collect()
+        })
+        .map_err(|_| Error::Generic("query panicked".to_string()))?;
+
+        let mut answers = Vec::new();
+        for result in results {
+            answers.extend(result.map_err(Error::Generic)?);
+        }
+        Ok(answers)
+    }
+}
+
+impl Blockchain for ProxiedEsplora {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        vec![Capability::FullHistory, Capability::GetAnyTx]
+            .into_iter()
+            .collect()
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        _stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), Error> {
+        // the wallet caches the scripts up to the address gap limit before syncing
+        let scripts = database.iter_script_pubkeys(None)?;
+        let mut statuses = HashMap::new();
+        for tx in self.query_all(&scripts, |script| self.history(script))? {
+            statuses.insert(tx.txid, tx.status);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let statuses: Vec<(String, Status)> = statuses.into_iter().collect();
+        let txs = self.query_all(&statuses, |(txid, status)| {
+            let tx = self
+                .raw_tx(txid)?
+                .ok_or_else(|| Error::Generic(format!("transaction {} not found", txid)))?;
+            Ok(WalletTx {
+                tx,
+                height: status.block_height.filter(|_| status.confirmed),
+                timestamp: status.block_time.unwrap_or(now),
+            })
+        })?;
+        store(database, &txs)
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        self.raw_tx(&txid.to_string())
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
+        self.client
+            .post(&format!("{}/tx", self.url))
+            .body(hex::encode(serialize(tx)))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map(|_| ())
+            .map_err(|e| Error::Generic(format!("{}/tx: {}", self.url, e)))
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        self.get_json("/blocks/tip/height")
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        let estimates: HashMap<String, f32> = self.get_json("/fee-estimates")?;
+        let mut estimates: Vec<(usize, f32)> = estimates
+            .into_iter()
+            .filter_map(|(blocks, rate)| Some((usize::from_str(&blocks).ok()?, rate)))
+            .collect();
+        estimates.sort_by_key(|(blocks, _)| *blocks);
+        // the estimate of the target, or else of the closest one confirming sooner
+        estimates
+            .iter()
+            .rev()
+            .find(|(blocks, _)| *blocks <= target)
+            .or_else(|| estimates.first())
+            .map(|(_, rate)| FeeRate::from_sat_per_vb(*rate))
+            .ok_or_else(|| Error::Generic(format!("{} has no fee estimates", self.url)))
+    }
+}
@@ -0,0 +1,248 @@
+use bdk::bitcoin::util::bip32::KeySource;
+use bdk::bitcoin::util::psbt::{Input, PartiallySignedTransaction};
+use bdk::bitcoin::{Network, PublicKey, Script};
+use bdk::database::MemoryDatabase;
+use bdk::wallet::AddressIndex;
+use bdk::Wallet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::errors::SweepError;
+use crate::summary::{address, fee, required_signatures, signed_vsize, spent_output};
+
+/// What a PSBT spends and pays, as printed by the inspect command
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Inspection {
+    pub txid: String,
+    pub locktime: u32,
+    pub inputs: Vec<InspectedInput>,
+    pub outputs: Vec<InspectedOutput>,
+    /// unless the value of an input is missing
+    pub fee: Option<u64>,
+    /// once signed, estimated for the inputs not finalized
+    pub vsize: Option<usize>,
+    /// sat/vB
+    pub fee_rate: Option<f32>,
+    /// every input is finalized
+    pub complete: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InspectedInput {
+    pub outpoint: String,
+    pub value: Option<u64>,
+    pub address: Option<String>,
+    pub sequence: u32,
+    /// unsigned, partially signed, signed or finalized
+    pub status: String,
+    pub signatures: usize,
+    /// unless the script type is unknown
+    pub required_signatures: Option<usize>,
+    pub bip32_derivations: Vec<Bip32Derivation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derived_from: Option<DerivedFrom>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct InspectedOutput {
+    pub value: u64,
+    pub address: String,
+    pub bip32_derivations: Vec<Bip32Derivation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derived_from: Option<DerivedFrom>,
+}
+
+/// Key of a script as the PSBT says, which nothing checks
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Bip32Derivation {
+    pub pubkey: String,
+    pub fingerprint: String,
+    pub path: String,
+}
+
+/// Descriptor a script was derived from, checked by deriving it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DerivedFrom {
+    pub descriptor: String,
+    pub index: u32,
+}
+
+/// Scripts of descriptors up to an index, mapped to the descriptor and the index
+pub struct Descriptors {
+    scripts: HashMap<Script, DerivedFrom>,
+}
+
+impl Descriptors {
+    pub fn new(descriptors: &[String], network: Network, count: u32) -> Result<Self, SweepError> {
+        let mut scripts = HashMap::new();
+        for descriptor in descriptors {
+            let wallet = Wallet::new_offline(descriptor, None, network, MemoryDatabase::default())?;
+            for index in 0..count {
+                let script = wallet
+                    .get_address(AddressIndex::Peek(index))?
+                    .address
+                    .script_pubkey();
+                // a descriptor without a wildcard has a single script
+                scripts.entry(script).or_insert_with(|| DerivedFrom {
+                    descriptor: descriptor.clone(),
+                    index,
+                });
+            }
+        }
+        Ok(Descriptors { scripts })
+    }
+
+    pub fn get(&self, script: &Script) -> Option<&DerivedFrom> {
+        self.scripts.get(script)
+    }
+}
+
+pub fn inspect(
+    psbt: &PartiallySignedTransaction,
+    network: Network,
+    descriptors: &Descriptors,
+) -> Inspection {
+    let tx = &psbt.global.unsigned_tx;
+
+    let inputs: Vec<InspectedInput> = tx
+        .input
+        .iter()
+        .zip(psbt.inputs.iter())
+        .enumerate()
+        .map(|(index, (txin, input))| {
+            let spent = spent_output(psbt, index);
+            let required = spent
+                .as_ref()
+                .and_then(|txout| required_signatures(input, &txout.script_pubkey));
+            InspectedInput {
+                outpoint: txin.previous_output.to_string(),
+                value: spent.as_ref().map(|txout| txout.value),
+                address: spent
+                    .as_ref()
+                    .map(|txout| address(&txout.script_pubkey, network)),
+                sequence: txin.sequence,
+                status: status(input, required).to_string(),
+                signatures: input.partial_sigs.len(),
+                required_signatures: required,
+                bip32_derivations: derivations(&input.bip32_derivation),
+                derived_from: spent
+                    .as_ref()
+                    .and_then(|txout| descriptors.get(&txout.script_pubkey))
+                    .cloned(),
+            }
+        })
+        .collect();
+
+    let outputs = tx
+        .output
+        .iter()
+        .zip(psbt.outputs.iter())
+        .map(|(txout, output)| InspectedOutput {
+            value: txout.value,
+            address: address(&txout.script_pubkey, network),
+            bip32_derivations: derivations(&output.bip32_derivation),
+            derived_from: descriptors.get(&txout.script_pubkey).cloned(),
+        })
+        .collect();
+
+    let fee = fee(psbt);
+    let vsize = signed_vsize(psbt);
+    Inspection {
+        txid: tx.txid().to_string(),
+        locktime: tx.lock_time,
+        complete: inputs.iter().all(|input| input.status == "finalized"),
+        inputs,
+        outputs,
+        fee,
+        vsize,
+        fee_rate: match (fee, vsize) {
+            (Some(fee), Some(vsize)) => Some(fee as f32 / vsize as f32),
+            _ => None,
+        },
+    }
+}
+
+fn status(input: &Input, required: Option<usize>) -> &'static str {
+    if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+        "finalized"
+    } else if input.partial_sigs.is_empty() {
+        "unsigned"
+    } else if input.partial_sigs.len() >= required.unwrap_or(usize::MAX) {
+        "signed"
+    } else {
+        "partially signed"
+    }
+}
+
+fn derivations(derivations: &BTreeMap<PublicKey, KeySource>) -> Vec<Bip32Derivation> {
+    derivations
+        .iter()
+        .map(|(pubkey, (fingerprint, path))| Bip32Derivation {
+            pubkey: pubkey.to_string(),
+            fingerprint: fingerprint.to_string(),
+            path: path.to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn inspect_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::{Address, OutPoint, Transaction, TxIn, TxOut};
+    use std::str::FromStr;
+
+    let e = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*)";
+    let descriptors = Descriptors::new(&[e.to_string()], Network::Regtest, 5)?;
+    // the first address of e
+    let own = Address::from_str("bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs").unwrap();
+    let other = Address::from_str("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26").unwrap();
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: 200,
+        input: vec![TxIn {
+            previous_output: OutPoint::from_str(
+                "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:0",
+            )
+            .unwrap(),
+            ..TxIn::default()
+        }],
+        output: vec![
+            TxOut {
+                value: 60_000,
+                script_pubkey: other.script_pubkey(),
+            },
+            TxOut {
+                value: 39_000,
+                script_pubkey: own.script_pubkey(),
+            },
+        ],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: 100_000,
+        script_pubkey: own.script_pubkey(),
+    });
+
+    let inspection = inspect(&psbt, Network::Regtest, &descriptors);
+    assert_eq!(inspection.locktime, 200);
+    assert_eq!(inspection.fee, Some(1_000));
+    assert!(!inspection.complete);
+
+    let input = &inspection.inputs[0];
+    assert_eq!(input.value, Some(100_000));
+    assert_eq!(input.status, "unsigned");
+    assert_eq!(input.required_signatures, Some(1));
+    assert_eq!(input.derived_from.as_ref().unwrap().index, 0);
+
+    assert!(inspection.outputs[0].derived_from.is_none());
+    assert_eq!(
+        inspection.outputs[1]
+            .derived_from
+            .as_ref()
+            .unwrap()
+            .descriptor,
+        e
+    );
+
+    Ok(())
+}
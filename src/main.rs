@@ -1,36 +1,69 @@
-use bdk::bitcoin::consensus::serialize;
-use bdk::bitcoin::Address;
-use bdk::blockchain::esplora::EsploraBlockchainConfig;
+use bdk::bitcoin::consensus::{deserialize, serialize};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Address, Script, Txid};
 use bdk::blockchain::noop_progress;
 use bdk::blockchain::Blockchain;
-use bdk::blockchain::{
-    AnyBlockchain, AnyBlockchainConfig, ConfigurableBlockchain, ElectrumBlockchainConfig,
-};
 use bdk::database::MemoryDatabase;
-use bdk::wallet::tx_builder;
-use bdk::{SignOptions, Wallet};
+use bdk::wallet::{tx_builder, AddressIndex};
+use bdk::{FeeRate, KeychainKind, SignOptions, TransactionDetails, Wallet};
 use clap::crate_version;
 use clap::{ArgGroup, Clap};
 use serde::{Deserialize, Serialize};
-use std::rc::Rc;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 mod ur;
-use ur::{is_ur_address, is_ur_descriptor, parse_ur_descriptor, psbt_as_ur};
+use ur::{
+    decode_ur, decode_ur_address, encode_as_ur, is_ur_account, is_ur_address, is_ur_descriptor,
+    is_ur_psbt, is_ur_signing_key, parse_ur_account_descriptors, parse_ur_descriptor,
+    parse_ur_psbt, parse_ur_signing_key, psbt_as_ur,
+};
 
 mod errors;
 use errors::SweepError;
 
-// parse the first integer in a string
-fn parse_int(input: &str) -> Option<u64> {
-    input
-        .chars()
-        .skip_while(|ch| !ch.is_digit(10))
-        .take_while(|ch| ch.is_digit(10))
-        .fold(None, |acc, ch| {
-            ch.to_digit(10).map(|b| acc.unwrap_or(0) * 10 + b as u64)
-        })
-}
+mod sign;
+use sign::{check_destinations, check_fee, missing_utxos, signing_descriptors};
+
+mod derivation;
+use derivation::DerivationMap;
+
+mod privacy;
+use privacy::{jitter_fee_rate, privacy_groups, script_type};
+
+mod timelock;
+use timelock::{required_locktime, sweep_locktime};
+
+mod plan;
+use plan::{parse_policy_path, Plan};
+
+mod bump;
+use bump::{
+    child_fee, clear_signatures, redistribute_fee, replacement_fee, signals_replaceability,
+};
+
+mod rpc;
+
+mod offline;
+use offline::UtxoSet;
+
+mod multi;
+
+mod esplora;
+
+mod backend;
+use backend::{parse_network, BackendOpt};
+
+mod config;
+use config::Config;
+
+mod summary;
+use summary::{review, spent_output};
+
+mod inspect;
+use inspect::{inspect, Descriptors};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct Psbt {
@@ -38,11 +71,51 @@ struct Psbt {
     ur: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct UrOutput {
+    ur: String,
+}
+
+/// Address derived from a descriptor (keychain "external") or a change descriptor ("internal")
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DerivedAddress {
+    address: String,
+    keychain: String,
+    index: u32,
+}
+
+impl DerivedAddress {
+    fn new(address: &Address, keychain: KeychainKind, index: u32) -> Self {
+        let keychain = match keychain {
+            KeychainKind::External => "external",
+            KeychainKind::Internal => "internal",
+        };
+        DerivedAddress {
+            address: address.to_string(),
+            keychain: keychain.to_string(),
+            index,
+        }
+    }
+}
+
+/// Which input is sent to which output when sweeping to a descriptor
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Mapping {
+    outpoint: String,
+    source: DerivedAddress,
+    destination: DerivedAddress,
+    value_in: u64,
+    value_out: u64,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct CliOutput {
     amount: u64,
     fees: u64,
     address: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mapping: Vec<Mapping>,
+    locktime: u32,
     timestamp: u64,
     txid: String,
     psbt: Psbt,
@@ -54,6 +127,21 @@ Result:
   "amount" : n,         (numeric) amount swept
   "fees" : n,           (numeric) miner fees [sats]
   "address" : ["str"]   (array of strings) destination address(es)
+  "mapping" : [         (array of json objects) only when sweeping to a descriptor,
+    {                   one for each input in the order of the outputs
+      "outpoint" : "str",      (string) input being swept, txid:vout
+      "source" : {             (json object) address of the input
+        "address" : "str",     (string)
+        "keychain" : "str",    (string) external (descriptor) or internal (change descriptor)
+        "index" : n            (numeric) derivation index
+      },
+      "destination" : {...},   (json object) address of the output, same keychain and index
+      "value_in" : n,          (numeric) value of the input [sats]
+      "value_out" : n          (numeric) value of the output [sats]
+    }, ...
+  ],
+  "locktime": n,        (numeric) nLockTime of the transaction, a block height or
+                        a unix timestamp if 500000000 or above
   "timestamp": n,       (numeric) unix timestamp of the PSBT created
   "txid" : "str",       (string) Transaction ID
   "psbt" : {            (json object)
@@ -61,27 +149,185 @@ Result:
      "ur" : "str"       (string) psbt in UR format
    }
 }
+
+When sweeping a crypto-account the result is an array of the above,
+one for each account descriptor holding funds.
+
+With --privacy the result is an array of the above as well, one for each
+UTXO (or address) of every descriptor. Each PSBT has a single output, so
+"value_out" of the inputs of an address is the value of their common output.
+
+With --allow-empty the result is {} (or [] for a crypto-account) if there
+is nothing to sweep.
 "#;
 
+const ABOUT_BUMP_FEE: &str = r#"
+Result:
+{                       (json object)
+  "amount" : n,         (numeric) amount swept
+  "fees" : n,           (numeric) miner fees of the replacement [sats]
+  "address" : ["str"]   (array of strings) destination address(es)
+  "mapping" : [{...}]   (array of json objects) only with -e and -s, as for the sweep,
+                        with the values of the replacement
+  "locktime": n,        (numeric) nLockTime of the transaction
+  "timestamp": n,       (numeric) unix timestamp of the PSBT created
+  "txid" : "str",       (string) Transaction ID of the replacement
+  "psbt" : {            (json object)
+     "base64" : "str",  (string) psbt in base64 format
+     "ur" : "str"       (string) psbt in UR format
+   }
+}
+
+The replacement spends the same inputs to the same outputs, each output paying an
+equal share of the extra fees, the way the sweep shared the fees between the UTXOs.
+An output that can't pay its share without falling below the dust limit fails the
+replacement with insufficient_funds."#;
+
+const ABOUT_CPFP: &str = r#"
+Result:
+{                       (json object)
+  "amount" : n,         (numeric) value of the output of the sweep spent
+  "fees" : n,           (numeric) miner fees of the child [sats]
+  "address" : ["str"]   (array of strings) destination address
+  "locktime": n,        (numeric) nLockTime of the transaction
+  "timestamp": n,       (numeric) unix timestamp of the PSBT created
+  "txid" : "str",       (string) Transaction ID of the child
+  "psbt" : {            (json object)
+     "base64" : "str",  (string) psbt in base64 format
+     "ur" : "str"       (string) psbt in UR format
+   }
+}
+
+The child spends the largest output of the sweep in the destination descriptor
+and pays enough fees for the sweep and the child together to reach the fee rate."#;
+
+const ABOUT_EXPORT_UTXOS: &str = r#"
+Result:
+{                       (json object)
+  "network" : "str",    (string) bitcoin, testnet or regtest
+  "height" : n,         (numeric) block height, the locktime of the sweep
+  "utxos" : [           (array of json objects)
+    {
+      "outpoint" : "str",   (string) txid:vout
+      "tx" : "str"          (string) transaction creating the UTXO, hex encoded
+    }
+  ]
+}
+
+Pass it to sweep --utxos-file with --fee-rate on the offline machine. A segwit
+UTXO can be given as "witness_utxo": {"value": n, "script_pubkey": "hex"}
+instead of its transaction."#;
+
 const ABOUT_PSBT_SIGN: &str = r#"
 Result:
 {                    (json object)
   "base64" : "str",  (string) signed psbt in base64 format
   "ur" : "str"       (string) signed psbt in UR format
+}
+
+With --expect-dest, --max-fee or --max-fee-rate nothing is signed unless the PSBT passes
+the checks, which fail with the signing error."#;
+
+const ABOUT_INSPECT: &str = r#"
+Result:
+{                             (json object)
+  "txid" : "str",             (string) id of the unsigned transaction
+  "locktime" : n,             (numeric)
+  "inputs" : [                (array of json objects)
+    {
+      "outpoint" : "str",     (string) txid:vout
+      "value" : n,            (numeric) sat, null if the PSBT has no UTXO for the input
+      "address" : "str",      (string) address spent, null as well
+      "sequence" : n,         (numeric)
+      "status" : "str",       (string) unsigned, partially signed, signed or finalized
+      "signatures" : n,       (numeric) partial signatures
+      "required_signatures" : n,  (numeric) null for an unknown script type
+      "bip32_derivations" : [ (array of json objects) keys of the input as the PSBT says
+        {"pubkey" : "hex", "fingerprint" : "hex", "path" : "m/..."}
+      ],
+      "derived_from" : {      (json object, optional) only if derived from a -d descriptor
+        "descriptor" : "str", (string)
+        "index" : n           (numeric)
+      }
+    }
+  ],
+  "outputs" : [               (array of json objects)
+    {
+      "value" : n,            (numeric) sat
+      "address" : "str",      (string) address, or the script in hex
+      "bip32_derivations" : [...],  (array of json objects) as for the inputs
+      "derived_from" : {...}  (json object, optional) as for the inputs
+    }
+  ],
+  "fee" : n,                  (numeric) sat, null if the value of an input is missing
+  "vsize" : n,                (numeric) vB once signed, null for an unknown script type
+  "fee_rate" : n,             (numeric) sat/vB once signed
+  "complete" : true|false     (boolean) every input is finalized
+}
+
+The BIP32 derivations are what the PSBT claims. Only "derived_from" is checked, by
+deriving the first -g scripts of the -d descriptors."#;
+
+const ABOUT_POLICY: &str = r#"
+Result:
+{                    (json object)
+  "external" : {...},  (json object) spending policy of the descriptor as shown by bdk. Every
+                       node has an "id", THRESH nodes list their "items"
+  "internal" : {...}   (json object) spending policy of the change descriptor, if given
+}
+
+A path through THRESH nodes, e.g. {"<id>": [0, 2]} for the items 0 and 2 of the node
+<id>, is passed to sweep with --policy-path."#;
+
+const ABOUT_UR_ENCODE: &str = r#"
+Result:
+{                 (json object)
+  "ur" : "str"    (string) crypto-output, crypto-hdkey or crypto-address in UR format
+}"#;
+
+const ABOUT_UR_DECODE: &str = r#"
+Result:
+{                    (json object)
+  "type" : "str",    (string) UR type, e.g. crypto-output
+  "cbor" : {...},    (json) decoded CBOR tree. Tags are shown as {"tag": n, "value": ...}
+                     and byte strings as hex
+  "core" : "str"     (string or array of strings) Bitcoin Core compatible representation:
+                     descriptor(s), key, address or base64 psbt
 }"#;
 
+const ABOUT_ERRORS: &str = r#"
+Errors are printed on stderr, with --json-errors as:
+{"error": {"code": n, "kind": "str", "message": "str"}}
+
+ code  kind                exit code
+    1  internal            1
+   10  cli                 2
+   20  network             3
+   21  backend             3
+   30  descriptor          4
+   31  address             4
+   32  ur                  4
+   33  psbt                4
+   40  fee                 5
+   41  insufficient_funds  5
+   42  gap_limit           5
+   43  nothing_to_sweep    6
+   44  not_replaceable     5
+   50  signing             7
+   60  aborted             8
+"#;
+
 #[derive(Clap, Debug)]
 #[clap(group = ArgGroup::new("destination").required(true))]
 struct CliInput {
-    /// Descriptor in UR format or in Bitcoin Core compatible format
+    /// Descriptor in UR format (crypto-output or crypto-account) or in Bitcoin Core compatible
+    /// format. All descriptors of a crypto-account are swept, each with its own PSBT
     #[clap(short = 'd')]
     descriptor: String,
-    /// Change descriptor in UR format or in Bitcoin core compatible format
+    /// Change descriptor in UR format or in Bitcoin core compatible format. Derived automatically
+    /// for a crypto-account
     #[clap(short = 'c')]
-    descriptor_chg: String,
-    /// Address gap limit to search within for available funds
-    #[clap(short = 'g', default_value = "20")]
-    address_gap_limit: u32,
+    descriptor_chg: Option<String>,
     /// Bitcoin address in UR format or in Bitcoin Core compatible format.
     #[clap(short, group = "destination")]
     address: Option<String>,
@@ -91,400 +337,1260 @@ struct CliInput {
     /// Destination change descriptor in UR format or in Bitcoin core compatible format
     #[clap(short = 's')]
     dest_descriptor_chg: Option<String>,
-    /// Target (number of blocks) used to estimate the fee rate for a PSBT
-    #[clap(short, default_value = "6")]
-    target: usize,
-    /// Bitcoin network
-    #[clap(short, default_value = "testnet", possible_values=&["mainnet", "testnet", "regtest"])]
-    network: String,
-    /// By default electrum server is used ssl://electrum.blockstream.info:60002 to query blockchain.
-    /// But you can override it with an esplora server of your choice
-    /// Examples: https://blockstream.info/testnet/api for testnet and https://blockstream.info/api for mainnet
-    #[clap(short = 'p', long, conflicts_with = "server")]
-    esplora: Option<String>,
-    /// Electrum server to query the blockchain. Default="ssl://electrum.blockstream.info:60002"
-    /// In regtest mode 127.0.0.1:51401 is hardcoded.
-    #[clap(long, default_value = "ssl://electrum.blockstream.info:60002")]
-    server: String,
-    /// You can pass a proxy e.g. localhost:9050 and then pass an onion address of an Electrum server
-    /// to the server arg, e.g.
-    /// explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion:143 for testnet
-    #[clap(long, conflicts_with = "esplora")]
-    proxy: Option<String>,
+    /// Target (number of blocks) used to estimate the fee rate for a PSBT [default: 6]
+    #[clap(short)]
+    target: Option<usize>,
+    /// Fee rate of the PSBT(s) [sat/vB]. Estimated for the target by default
+    #[clap(long, conflicts_with = "target")]
+    fee_rate: Option<f32>,
+    #[clap(flatten)]
+    backend: BackendOpt,
+    /// UTXOs to sweep as exported by the export-utxos command, to build the PSBT(s) offline
+    /// without connecting to a server. Requires the fee rate
+    #[clap(long, requires = "fee-rate")]
+    utxos_file: Option<PathBuf>,
+    /// Spending path of a miniscript descriptor with several, e.g. the recovery key after a
+    /// timelock, as JSON: {"<policy id>": [<index of the item>, ...], ...}. The policy ids are
+    /// shown by the policy command. Fees are computed for the satisfaction of this path
+    #[clap(long)]
+    policy_path: Option<String>,
+    /// nLockTime of the PSBT(s), a block height or a unix timestamp if 500000000 or above. They
+    /// can't be mined before. Defaults to the current block height against fee sniping, or the
+    /// timelock required by the descriptor if later. 0 disables the locktime
+    #[clap(long)]
+    locktime: Option<u32>,
+    /// Sweep with one PSBT per UTXO, or per address (UTXOs of an address are already linked),
+    /// instead of linking the whole wallet in one transaction. Requires a destination descriptor,
+    /// so every PSBT pays to a different address
+    #[clap(long, possible_values=&["utxo", "address"], requires = "dest-descriptor")]
+    privacy: Option<String>,
+    /// Raise the fee rate of every PSBT by a random amount of up to this percentage
+    #[clap(long, requires = "privacy")]
+    fee_jitter: Option<u32>,
+    /// Check that every output has the script type of its inputs, e.g. p2wpkh to p2wpkh, and
+    /// fail otherwise. Only a check: the outputs aren't derived with the script type of the
+    /// inputs, pass a destination descriptor of the same type
+    #[clap(long, requires = "privacy")]
+    check_script_type: bool,
+    /// Exit successfully with an empty result ({}, or [] for a crypto-account) instead of an
+    /// error when there is nothing to sweep
+    #[clap(long)]
+    allow_empty: bool,
+    /// Show a summary of the PSBT(s) on stderr and ask for a confirmation before printing them
+    #[clap(long)]
+    interactive: bool,
 }
 
 #[derive(Clap, Debug)]
-struct SignPSBT {
-    /// Private descriptor in Bitcoin Core compatible format
+#[clap(group = ArgGroup::new("original").required(true))]
+struct BumpFee {
+    /// Descriptor of the sweep in UR format or in Bitcoin Core compatible format
+    #[clap(short = 'd')]
+    descriptor: String,
+    /// Change descriptor in UR format or in Bitcoin core compatible format
+    #[clap(short = 'c')]
+    descriptor_chg: String,
+    /// PSBT of the sweep in base64 format, as returned by the sweep command
+    #[clap(long, group = "original")]
+    psbt: Option<String>,
+    /// Transaction ID of the sweep
+    #[clap(long, group = "original")]
+    txid: Option<String>,
+    /// Fee rate of the replacement [sat/vB]. Estimated for the target by default
+    #[clap(long, conflicts_with = "target")]
+    fee_rate: Option<f32>,
+    /// Target (number of blocks) used to estimate the fee rate of the replacement [default: 6]
+    #[clap(short)]
+    target: Option<usize>,
+    /// Destination descriptor of the sweep in UR format or in Bitcoin Core compatible format.
+    /// Reports the mapping of the inputs to the outputs of the replacement
+    #[clap(short = 'e', requires = "dest-descriptor-chg")]
+    dest_descriptor: Option<String>,
+    /// Destination change descriptor of the sweep in UR format or in Bitcoin core compatible format
+    #[clap(short = 's', requires = "dest-descriptor")]
+    dest_descriptor_chg: Option<String>,
+    /// Show a summary of the PSBT on stderr and ask for a confirmation before printing it
+    #[clap(long)]
+    interactive: bool,
+    #[clap(flatten)]
+    backend: BackendOpt,
+}
+
+#[derive(Clap, Debug)]
+struct Cpfp {
+    /// Destination descriptor of the sweep in UR format or in Bitcoin Core compatible format
+    #[clap(short = 'e')]
+    dest_descriptor: String,
+    /// Destination change descriptor of the sweep in UR format or in Bitcoin core compatible format
+    #[clap(short = 's')]
+    dest_descriptor_chg: String,
+    /// Transaction ID of the unconfirmed sweep
+    #[clap(long, required = true)]
+    txid: String,
+    /// Bitcoin address in UR format or in Bitcoin Core compatible format receiving the child's
+    /// output. Defaults to the address of the output spent, so the pairing of the sweep is kept
+    #[clap(short)]
+    address: Option<String>,
+    /// Fee rate of the sweep and the child together [sat/vB]. Estimated for the target by default
+    #[clap(long, conflicts_with = "target")]
+    fee_rate: Option<f32>,
+    /// Target (number of blocks) used to estimate the fee rate [default: 6]
+    #[clap(short)]
+    target: Option<usize>,
+    /// Show a summary of the PSBT on stderr and ask for a confirmation before printing it
+    #[clap(long)]
+    interactive: bool,
+    #[clap(flatten)]
+    backend: BackendOpt,
+}
+
+#[derive(Clap, Debug)]
+struct ExportUtxos {
+    /// Descriptor in UR format or in Bitcoin Core compatible format
     #[clap(short = 'd')]
     descriptor: String,
-    /// Private change descriptor in Bitcoin core compatible format
+    /// Change descriptor in UR format or in Bitcoin core compatible format
     #[clap(short = 'c')]
     descriptor_chg: String,
-    /// PSBT in Bitcoin Core compatible format
+    #[clap(flatten)]
+    backend: BackendOpt,
+}
+
+#[derive(Clap, Debug)]
+struct SignPSBT {
+    /// Private descriptor in Bitcoin Core compatible format or in UR format: crypto-output with
+    /// private keys, private crypto-hdkey or crypto-seed. For crypto-hdkey and crypto-seed the
+    /// script type and derivation path are taken from the PSBT inputs
+    #[clap(short = 'd')]
+    descriptor: String,
+    /// Private change descriptor in Bitcoin core compatible format or crypto-output in UR format
+    #[clap(short = 'c')]
+    descriptor_chg: Option<String>,
+    /// PSBT in Bitcoin Core compatible format or crypto-psbt in UR format
+    #[clap(required = true)]
+    psbt: String,
+    /// Bitcoin network
+    #[clap(short, default_value = "testnet", possible_values=&["mainnet", "testnet", "regtest"])]
+    network: String,
+    /// Show a summary of the PSBT on stderr and ask for a confirmation before signing it
+    #[clap(long)]
+    interactive: bool,
+    /// Refuse to sign unless every output pays this address or is derived from this descriptor,
+    /// in Bitcoin Core compatible format or in UR format. Can be repeated, e.g. for a change
+    /// descriptor
+    #[clap(long)]
+    expect_dest: Vec<String>,
+    /// Number of addresses of each --expect-dest descriptor to check
+    #[clap(short = 'g', default_value = "1000")]
+    count: u32,
+    /// Refuse to sign if the fee is over this amount [sat]
+    #[clap(long)]
+    max_fee: Option<u64>,
+    /// Refuse to sign if the fee rate once signed is over this rate [sat/vB]
+    #[clap(long)]
+    max_fee_rate: Option<f32>,
+}
+
+#[derive(Clap, Debug)]
+struct Inspect {
+    /// PSBT in Bitcoin Core compatible format or crypto-psbt in UR format
     #[clap(required = true)]
     psbt: String,
+    /// Descriptor, crypto-output or crypto-account in UR format to check the inputs and the
+    /// outputs against. Can be repeated
+    #[clap(short = 'd')]
+    descriptor: Vec<String>,
+    /// Number of addresses of each descriptor to check
+    #[clap(short = 'g', default_value = "1000")]
+    count: u32,
     /// Bitcoin network
     #[clap(short, default_value = "testnet", possible_values=&["mainnet", "testnet", "regtest"])]
     network: String,
 }
 
 #[derive(Clap, Debug)]
-#[clap(version=crate_version!())]
+struct ShowPolicy {
+    /// Descriptor in UR format or in Bitcoin Core compatible format
+    #[clap(short = 'd')]
+    descriptor: String,
+    /// Change descriptor in UR format or in Bitcoin core compatible format
+    #[clap(short = 'c')]
+    descriptor_chg: Option<String>,
+    /// Bitcoin network
+    #[clap(short, default_value = "testnet", possible_values=&["mainnet", "testnet", "regtest"])]
+    network: String,
+}
+
+#[derive(Serialize, Debug)]
+struct PolicyOutput {
+    external: Option<bdk::descriptor::policy::Policy>,
+    internal: Option<bdk::descriptor::policy::Policy>,
+}
+
+#[derive(Clap, Debug)]
+struct UrEncode {
+    /// Output descriptor, extended key with an optional origin (e.g. [c258d2e4/84h/1h/0h]tpub...)
+    /// or address in Bitcoin Core compatible format. URs have no regtest network, regtest keys
+    /// and addresses are encoded as testnet ones, so a bcrt1 address decodes as tb1
+    #[clap(required = true)]
+    input: String,
+}
+
+#[derive(Clap, Debug)]
+struct UrDecode {
+    /// UR of type crypto-output, crypto-hdkey, crypto-eckey, crypto-address, crypto-psbt,
+    /// crypto-account or crypto-seed
+    #[clap(required = true)]
+    ur: String,
+    /// Bitcoin network. Used for crypto-eckey and crypto-seed, which don't specify one
+    #[clap(short, default_value = "testnet", possible_values=&["mainnet", "testnet", "regtest"])]
+    network: String,
+}
+
+#[derive(Clap, Debug)]
+enum UrCommand {
+    /// Encode an output descriptor, an extended key or an address to UR
+    #[clap(verbatim_doc_comment, after_help=ABOUT_UR_ENCODE)]
+    Encode(UrEncode),
+    /// Decode and validate a UR of any supported type
+    #[clap(verbatim_doc_comment, after_help=ABOUT_UR_DECODE)]
+    Decode(UrDecode),
+}
+
+#[derive(Clap, Debug)]
+struct UrOpt {
+    #[clap(subcommand)]
+    cmd: UrCommand,
+}
+
+#[derive(Clap, Debug)]
 enum Opt {
     /// Sweep from a Bitcoin output descriptor
     #[clap(verbatim_doc_comment, after_help=ABOUT_SWEEP)]
     Sweep(CliInput),
+    /// Replace an unconfirmed sweep with one paying a higher fee (RBF)
+    #[clap(verbatim_doc_comment, after_help=ABOUT_BUMP_FEE)]
+    BumpFee(BumpFee),
+    /// Accelerate an unconfirmed sweep to a descriptor by spending one of its outputs (CPFP)
+    #[clap(verbatim_doc_comment, after_help=ABOUT_CPFP)]
+    Cpfp(Cpfp),
+    /// Export the UTXOs of a descriptor for a sweep on an offline machine (--utxos-file)
+    #[clap(verbatim_doc_comment, after_help=ABOUT_EXPORT_UTXOS)]
+    ExportUtxos(ExportUtxos),
     /// Sign a PSBT
     #[clap(verbatim_doc_comment, after_help=ABOUT_PSBT_SIGN)]
     Sign(SignPSBT),
+    /// Decode a PSBT: its inputs, outputs, fee, signatures and the descriptors they belong to
+    #[clap(verbatim_doc_comment, after_help=ABOUT_INSPECT)]
+    Inspect(Inspect),
+    /// Show the spending policy of a descriptor, with the ids for --policy-path
+    #[clap(verbatim_doc_comment, after_help=ABOUT_POLICY)]
+    Policy(ShowPolicy),
+    /// Convert between UR and Bitcoin Core compatible formats
+    Ur(UrOpt),
 }
 
-fn main() -> Result<(), SweepError> {
-    let matches = Opt::parse();
+#[derive(Clap, Debug)]
+#[clap(version=crate_version!(), after_help=ABOUT_ERRORS)]
+struct Cli {
+    /// Print errors as JSON on stderr
+    #[clap(long, global = true)]
+    #[allow(dead_code)] // read from the raw arguments in main, so usage errors are covered too
+    json_errors: bool,
+    #[clap(subcommand)]
+    cmd: Opt,
+}
 
-    match matches {
-        Opt::Sign(cmd) => {
-            let netw = if cmd.network == "mainnet" {
-                bdk::bitcoin::Network::Bitcoin
-            } else {
-                bdk::bitcoin::Network::Testnet
-            };
+fn cli_output(
+    psbt: &PartiallySignedTransaction,
+    details: &TransactionDetails,
+    address: Vec<String>,
+    mapping: Vec<Mapping>,
+) -> Result<CliOutput, SweepError> {
+    Ok(CliOutput {
+        amount: details.sent,
+        fees: details.fees,
+        address,
+        mapping,
+        locktime: psbt.global.unsigned_tx.lock_time,
+        timestamp: details.timestamp,
+        txid: details.txid.to_string(),
+        psbt: Psbt {
+            base64: base64::encode(&serialize(psbt)),
+            ur: psbt_as_ur(serialize(psbt))?,
+        },
+    })
+}
 
-            use bdk::bitcoin::consensus::deserialize;
-            let wallet = Wallet::new_offline(
-                &cmd.descriptor,
-                Some(&cmd.descriptor_chg),
-                netw,
-                MemoryDatabase::default(),
-            )?;
+/// Sweep a single source descriptor pair. Returns one output for each PSBT, i.e. a single one
+/// unless sweeping with `--privacy`, and none if there is nothing to sweep.
+fn sweep(
+    opt: &CliInput,
+    descriptor: &str,
+    descriptor_chg: &str,
+) -> Result<Vec<CliOutput>, SweepError> {
+    let mut dest_addresses: Vec<String> = Vec::new();
+    let mut mapping: Vec<Mapping> = Vec::new();
 
-            use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
-            let mut psbt: PartiallySignedTransaction =
-                deserialize(&base64::decode(&cmd.psbt).unwrap()).unwrap();
+    let policy_path = opt
+        .policy_path
+        .as_deref()
+        .map(parse_policy_path)
+        .transpose()?;
 
-            let _finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+    let netw = opt.backend.network();
+    let wallet = match opt.utxos_file {
+        Some(ref path) => {
+            opt.backend
+                .offline_wallet(descriptor, descriptor_chg, &UtxoSet::load(path)?)?
+        }
+        None => opt.backend.wallet(descriptor, descriptor_chg)?,
+    };
 
-            let out = Psbt {
-                base64: base64::encode(&serialize(&psbt)),
-                ur: psbt_as_ur(serialize(&psbt))?,
-            };
+    // user is sweeping to an output descriptor
+    let wallet_source = Wallet::new_offline(descriptor, None, netw, MemoryDatabase::default())?;
 
-            println!("{}", serde_json::to_string(&out)?);
+    let wallet_source_chg =
+        Wallet::new_offline(descriptor_chg, None, netw, MemoryDatabase::default())?;
+
+    let feerate = match opt.fee_rate {
+        Some(rate) => FeeRate::from_sat_per_vb(rate),
+        None => opt.backend.fee_rate(wallet.client(), opt.target)?,
+    };
+
+    wallet.sync(noop_progress(), Some(opt.backend.stop_gap()))?;
+
+    // fees are estimated for the satisfaction of the selected path, and by bdk for the
+    // largest satisfaction of the descriptor otherwise
+    let plan = policy_path
+        .as_ref()
+        .map(|path| Plan::new(&wallet, path))
+        .transpose()?;
+
+    // timelocks of the descriptor, e.g. after() and older(), are applied by bdk. A later
+    // locktime delays the sweep, e.g. for an inheritance
+    let locktime = sweep_locktime(
+        opt.locktime,
+        required_locktime(&wallet, &policy_path.clone().unwrap_or_default())?,
+        wallet.client().get_height()?,
+    )?;
+
+    let unspent = wallet.list_unspent()?;
+    if unspent.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Is user sweeping to an address or to an output descriptor?
+    let (mut psbt, details) = if let Some(ref addr) = opt.address {
+        let addr = if is_ur_address(addr.to_string()) {
+            decode_ur_address(addr.to_string())?
+        } else {
+            Address::from_str(&addr)?
+        };
+
+        dest_addresses.push(addr.to_string());
+
+        {
+            // build a PSBT sweeping to an address
+            let mut builder = wallet.build_tx();
+            builder.drain_wallet();
+            builder
+                .set_single_recipient(addr.script_pubkey())
+                .enable_rbf()
+                .nlocktime(locktime);
+            if let Some(ref path) = policy_path {
+                builder
+                    .policy_path(path.clone(), KeychainKind::External)
+                    .policy_path(path.clone(), KeychainKind::Internal);
+            }
+            if let Some(ref plan) = plan {
+                let keychains: Vec<KeychainKind> = unspent.iter().map(|u| u.keychain).collect();
+                builder.fee_absolute(plan.fee(&keychains, &[addr.script_pubkey()], feerate));
+            } else {
+                builder.fee_rate(feerate);
+            }
+            builder.finish()?
         }
-        Opt::Sweep(opt) => {
-            // TODO remove this when STDIN support implemented
-            let descriptor = Some(opt.descriptor);
-            let descriptor = if let Some(ref desc) = descriptor {
-                if is_ur_descriptor(desc.to_string()) {
-                    // this is UR format
-                    parse_ur_descriptor(desc.to_string())?
-                } else {
-                    // this is bitcoin core compatible format
-                    desc.to_string()
-                }
+    } else {
+        // build a PSBT sweeping to an output descriptor
+        // We are gonna prepare here individual wallets (descriptor, descriptor_chg, descriptor_destination,
+        // descriptor_destination_chg) so we can easily
+        // search for address indices when mapping UTXOs from a source descriptor to a
+        // destination descriptor
+
+        // TODO remove this when STDIN support implemented
+        let descriptor = {
+            let desc = opt.dest_descriptor.clone().ok_or_else(|| {
+                SweepError::Cli("Destination address or descriptor is required".to_string())
+            })?;
+            if is_ur_descriptor(desc.clone()) {
+                // this is UR format
+                parse_ur_descriptor(desc, netw)?
             } else {
-                return Err(SweepError::new(
-                    "cli arg".to_string(),
-                    "UR descriptor cannot be currently passed via STDIN. Pass it as a CLI arg"
-                        .to_string(),
-                ));
+                // this is bitcoin core compatible format
+                desc
+            }
+        };
+
+        // TODO remove this when STDIN support implemented
+        let descriptor_chg = {
+            let desc = opt.dest_descriptor_chg.clone().ok_or_else(|| {
+                SweepError::Cli("Destination change descriptor (-s) is required".to_string())
+            })?;
+            if is_ur_descriptor(desc.to_string()) {
+                // this is UR format
+                parse_ur_descriptor(desc, netw)?
+            } else {
+                // this is bitcoin core compatible format
+                desc
+            }
+        };
+
+        // user is sweeping to an output descriptor
+        let descriptor_destination =
+            Wallet::new_offline(&descriptor, None, netw, MemoryDatabase::default())?;
+
+        let descriptor_destination_chg =
+            Wallet::new_offline(&descriptor_chg, None, netw, MemoryDatabase::default())?;
+
+        // each UTXO is sent to the destination address with the same keychain and index.
+        // The sync can find UTXOs beyond the gap limit when earlier addresses were used,
+        // so the search follows the history the same way
+        let used: HashSet<Script> = wallet
+            .list_transactions(true)?
+            .into_iter()
+            .filter_map(|details| details.transaction)
+            .flat_map(|tx| tx.output)
+            .map(|txout| txout.script_pubkey)
+            .collect();
+        let scripts: Vec<Script> = unspent
+            .iter()
+            .map(|u| u.txout.script_pubkey.clone())
+            .collect();
+        let mut source_map =
+            DerivationMap::new(&wallet_source, &wallet_source_chg, opt.backend.stop_gap())?;
+        source_map.cover(
+            &wallet_source,
+            &wallet_source_chg,
+            &scripts,
+            &used,
+            opt.backend.stop_gap(),
+        )?;
+        let mut destinations = Vec::new();
+        for u in &unspent {
+            let address_source =
+                Address::from_script(&u.txout.script_pubkey, netw).ok_or_else(|| {
+                    SweepError::Descriptor(format!(
+                        "unsupported script of UTXO {}: {}",
+                        u.outpoint, u.txout.script_pubkey
+                    ))
+                })?;
+            let (keychain, index) = source_map.get(&u.txout.script_pubkey).ok_or_else(|| {
+                SweepError::GapLimit(format!(
+                    "UTXO {} not found in the output descriptor. Maybe increase the address gap limit",
+                    u.outpoint
+                ))
+            })?;
+            let address_dest = match keychain {
+                KeychainKind::External => {
+                    descriptor_destination.get_address(AddressIndex::Peek(index))?
+                }
+                KeychainKind::Internal => {
+                    descriptor_destination_chg.get_address(AddressIndex::Peek(index))?
+                }
             };
+            dest_addresses.push(address_dest.to_string());
+            mapping.push(Mapping {
+                outpoint: u.outpoint.to_string(),
+                source: DerivedAddress::new(&address_source, keychain, index),
+                destination: DerivedAddress::new(&address_dest.address, keychain, index),
+                value_in: u.txout.value,
+                // known once the fees are
+                value_out: 0,
+            });
+            destinations.push(address_dest.address);
+        }
 
-            // TODO remove this when STDIN support implemented
-            let descriptor_chg = Some(opt.descriptor_chg);
-            let descriptor_chg = if let Some(ref desc) = descriptor_chg {
-                if is_ur_descriptor(desc.to_string()) {
-                    // this is UR format
-                    parse_ur_descriptor(desc.to_string())?
+        if let Some(ref mode) = opt.privacy {
+            if opt.check_script_type {
+                for (u, address_dest) in unspent.iter().zip(&destinations) {
+                    let (source, dest) = (
+                        script_type(&u.txout.script_pubkey),
+                        script_type(&address_dest.script_pubkey()),
+                    );
+                    if source != dest {
+                        return Err(SweepError::ScriptType(format!(
+                            "UTXO {} is {} but its destination {} is {}, pass a destination descriptor of the same type",
+                            u.outpoint, source, address_dest, dest
+                        )));
+                    }
+                }
+            }
+
+            // the UTXOs of a group share the source address and so the destination address
+            let mut outs = Vec::new();
+            for group in privacy_groups(&unspent, mode) {
+                let address_dest = &destinations[group[0]];
+                let mut builder = wallet.build_tx();
+                builder.manually_selected_only();
+                for i in &group {
+                    builder.add_utxo(unspent[*i].outpoint)?;
+                }
+                builder
+                    .set_single_recipient(address_dest.script_pubkey())
+                    .enable_rbf()
+                    .nlocktime(locktime);
+                if let Some(ref path) = policy_path {
+                    builder
+                        .policy_path(path.clone(), KeychainKind::External)
+                        .policy_path(path.clone(), KeychainKind::Internal);
+                }
+                let feerate = jitter_fee_rate(feerate, opt.fee_jitter.unwrap_or(0));
+                if let Some(ref plan) = plan {
+                    let keychains: Vec<KeychainKind> =
+                        group.iter().map(|i| unspent[*i].keychain).collect();
+                    builder.fee_absolute(plan.fee(
+                        &keychains,
+                        &[address_dest.script_pubkey()],
+                        feerate,
+                    ));
                 } else {
-                    // this is bitcoin core compatible format
-                    desc.to_string()
+                    builder.fee_rate(feerate);
                 }
-            } else {
-                return Err(SweepError::new(
-                    "cli arg".to_string(),
-                    "UR descriptor cannot be currently passed via STDIN. Pass it as a CLI arg"
-                        .to_string(),
-                ));
-            };
+                let (mut psbt, details) = builder.finish()?;
+                wallet.client().complete_psbt(&mut psbt);
 
-            let mut dest_addresses: Vec<String> = Vec::new();
+                let value_out = psbt.global.unsigned_tx.output[0].value;
+                let group_mapping = group
+                    .iter()
+                    .map(|i| Mapping {
+                        value_out,
+                        ..mapping[*i].clone()
+                    })
+                    .collect();
+                outs.push(cli_output(
+                    &psbt,
+                    &details,
+                    vec![address_dest.to_string()],
+                    group_mapping,
+                )?);
+            }
+            return Ok(outs);
+        }
 
-            let mut client_url = opt.server;
-            let netw = if opt.network == "mainnet" {
-                bdk::bitcoin::Network::Bitcoin
-            } else if opt.network == "testnet" {
-                bdk::bitcoin::Network::Testnet
+        {
+            let fee_per_utxo = if let Some(ref plan) = plan {
+                let keychains: Vec<KeychainKind> = unspent.iter().map(|u| u.keychain).collect();
+                let outputs: Vec<Script> = destinations.iter().map(|a| a.script_pubkey()).collect();
+                let fee = plan.fee(&keychains, &outputs, feerate);
+                (fee + unspent.len() as u64 - 1) / unspent.len() as u64
             } else {
-                client_url = "127.0.0.1:51401".to_string();
-                bdk::bitcoin::Network::Regtest
+                // here we construct a psbt with zero fees so we can determine Tx size
+                // Based on Tx size we can construct a rael psbt with real fees in the next stage
+                let mut builder = wallet.build_tx();
+                for (u, address_dest) in unspent.iter().zip(&destinations) {
+                    builder
+                        .manually_selected_only()
+                        .add_utxo(u.outpoint)?
+                        .ordering(tx_builder::TxOrdering::Untouched)
+                        .add_recipient(address_dest.script_pubkey(), u.txout.value)
+                        .enable_rbf();
+                }
+                builder.nlocktime(locktime).fee_rate(feerate);
+
+                // every output spends its whole input, so the shortfall is what the fees cost
+                match builder.finish() {
+                    Err(bdk::Error::InsufficientFunds { needed, available }) => {
+                        needed.saturating_sub(available) / unspent.len() as u64
+                    }
+                    Err(e) => return Err(e.into()),
+                    // nothing is missing, e.g. with a zero fee rate
+                    Ok(_) => 0,
+                }
             };
 
-            let config_electrum = AnyBlockchainConfig::Electrum(ElectrumBlockchainConfig {
-                url: client_url,
-                socks5: opt.proxy,
-                retry: 2,
-                timeout: None,
-            });
+            // Now  we can construct a PSBT with real fees:
+            let mut builder = wallet.build_tx();
+            if let Some(ref path) = policy_path {
+                builder
+                    .policy_path(path.clone(), KeychainKind::External)
+                    .policy_path(path.clone(), KeychainKind::Internal);
+            }
+            let mut fee_combined = 0;
+            for ((u, address_dest), m) in unspent.iter().zip(&destinations).zip(mapping.iter_mut())
+            {
+                let recipient_amount = if u.txout.value > fee_per_utxo {
+                    fee_combined += fee_per_utxo;
+                    u.txout.value - fee_per_utxo
+                } else {
+                    fee_combined += u.txout.value;
+                    0
+                };
+                m.value_out = recipient_amount;
 
-            let config_esplora = opt.esplora.map(|e| {
-                AnyBlockchainConfig::Esplora(EsploraBlockchainConfig {
-                    base_url: e,
-                    concurrency: Some(4),
-                })
-            });
+                builder
+                    .manually_selected_only()
+                    .add_utxo(u.outpoint)?
+                    .ordering(tx_builder::TxOrdering::Untouched)
+                    .add_recipient(address_dest.script_pubkey(), recipient_amount)
+                    .enable_rbf();
+            }
+            builder.nlocktime(locktime).fee_absolute(fee_combined);
+            builder.finish()?
+        }
+    };
 
-            let config = config_esplora.unwrap_or(config_electrum);
+    wallet.client().complete_psbt(&mut psbt);
 
-            let wallet = Wallet::new(
-                &descriptor,
-                Some(&descriptor_chg),
-                netw,
-                MemoryDatabase::default(),
-                AnyBlockchain::from_config(&config)?,
-            )?;
+    /*
+        println!(
+            "DEBUG psbt: {}",
+            serde_json::to_string_pretty(&psbt).unwrap()
+        );
+    */
 
-            // user is sweeping to an output descriptor
-            let wallet_source = Rc::new(Wallet::new_offline(
-                &descriptor,
-                None,
-                netw,
-                MemoryDatabase::default(),
-            )?);
+    Ok(vec![cli_output(&psbt, &details, dest_addresses, mapping)?])
+}
+
+/// PSBT in base64 or crypto-psbt in UR format
+fn decode_psbt(psbt: &str) -> Result<PartiallySignedTransaction, SweepError> {
+    let psbt = if is_ur_psbt(psbt) {
+        parse_ur_psbt(psbt)?
+    } else {
+        base64::decode(psbt).map_err(|e| SweepError::Psbt(format!("invalid base64: {}", e)))?
+    };
+    Ok(deserialize(&psbt)?)
+}
 
-            let wallet_source_chg = Rc::new(Wallet::new_offline(
-                &descriptor_chg,
-                None,
+/// Descriptor in Bitcoin Core compatible format, decoded if in UR format
+fn core_descriptor(desc: &str, network: bdk::bitcoin::Network) -> Result<String, SweepError> {
+    if is_ur_descriptor(desc.to_string()) {
+        parse_ur_descriptor(desc.to_string(), network)
+    } else {
+        Ok(desc.to_string())
+    }
+}
+
+/// UTXOs of a descriptor pair with the transactions creating them, for an offline sweep
+fn export_utxos(cmd: &ExportUtxos) -> Result<UtxoSet, SweepError> {
+    let netw = cmd.backend.network();
+    let wallet = cmd.backend.wallet(
+        &core_descriptor(&cmd.descriptor, netw)?,
+        &core_descriptor(&cmd.descriptor_chg, netw)?,
+    )?;
+    wallet.sync(noop_progress(), Some(cmd.backend.stop_gap()))?;
+
+    let mut set = UtxoSet::new(netw, wallet.client().get_height()?);
+    let txs = wallet.list_transactions(true)?;
+    for u in wallet.list_unspent()? {
+        let tx = txs
+            .iter()
+            .find(|details| details.txid == u.outpoint.txid)
+            .and_then(|details| details.transaction.as_ref())
+            .ok_or_else(|| {
+                SweepError::Backend(format!(
+                    "transaction {} not returned by the server",
+                    u.outpoint.txid
+                ))
+            })?;
+        set.push(u.outpoint, tx);
+    }
+    Ok(set)
+}
+
+/// Replace an unconfirmed sweep with one spending the same inputs to the same outputs at a
+/// higher fee
+fn bump_fee(cmd: &BumpFee) -> Result<CliOutput, SweepError> {
+    let netw = cmd.backend.network();
+    let original = cmd.psbt.as_deref().map(decode_psbt).transpose()?;
+    let txid = match (&original, &cmd.txid) {
+        (Some(psbt), _) => psbt.global.unsigned_tx.txid(),
+        (None, Some(txid)) => Txid::from_str(txid)
+            .map_err(|e| SweepError::Cli(format!("invalid txid {}: {}", txid, e)))?,
+        (None, None) => {
+            return Err(SweepError::Cli(
+                "The PSBT (--psbt) or the txid (--txid) of the sweep is required".to_string(),
+            ))
+        }
+    };
+
+    let wallet = cmd.backend.wallet(
+        &core_descriptor(&cmd.descriptor, netw)?,
+        &core_descriptor(&cmd.descriptor_chg, netw)?,
+    )?;
+    let estimate = match cmd.fee_rate {
+        // the requested rate is used instead
+        Some(rate) => FeeRate::from_sat_per_vb(rate),
+        None => cmd.backend.fee_rate(wallet.client(), cmd.target)?,
+    };
+    wallet.sync(noop_progress(), Some(cmd.backend.stop_gap()))?;
+
+    let details = wallet
+        .list_transactions(true)?
+        .into_iter()
+        .find(|details| details.txid == txid)
+        .ok_or_else(|| {
+            SweepError::NotReplaceable(format!(
+                "transaction {} not found in the wallet, was it broadcast?",
+                txid
+            ))
+        })?;
+    if details.height.is_some() {
+        return Err(SweepError::NotReplaceable(format!(
+            "transaction {} is already confirmed",
+            txid
+        )));
+    }
+    let tx = details.transaction.clone().ok_or_else(|| {
+        SweepError::Backend(format!("transaction {} not returned by the server", txid))
+    })?;
+    if !signals_replaceability(&tx) {
+        return Err(SweepError::NotReplaceable(format!(
+            "transaction {} doesn't signal replaceability (BIP125)",
+            txid
+        )));
+    }
+    // the replacement has the same size as the signed original
+    let vsize = (tx.get_weight() as u64 + 3) / 4;
+
+    let mut psbt = match original {
+        Some(psbt) => psbt,
+        None => {
+            // the original again, with what it takes to sign it
+            let mut builder = wallet.build_fee_bump(txid)?;
+            builder
+                .fee_absolute(details.fees)
+                .ordering(tx_builder::TxOrdering::Untouched);
+            builder.finish()?.0
+        }
+    };
+    clear_signatures(&mut psbt);
+
+    let fee = replacement_fee(details.fees, vsize, cmd.fee_rate, estimate)?;
+    let fees = details.fees + redistribute_fee(&mut psbt, fee - details.fees)?;
+
+    let mapping = match (&cmd.dest_descriptor, &cmd.dest_descriptor_chg) {
+        (Some(dest), Some(dest_chg)) => {
+            let used: HashSet<Script> = wallet
+                .list_transactions(true)?
+                .into_iter()
+                .filter_map(|details| details.transaction)
+                .flat_map(|tx| tx.output)
+                .map(|txout| txout.script_pubkey)
+                .collect();
+            replacement_mapping(
+                &psbt,
+                (
+                    &core_descriptor(&cmd.descriptor, netw)?,
+                    &core_descriptor(&cmd.descriptor_chg, netw)?,
+                ),
+                (
+                    &core_descriptor(dest, netw)?,
+                    &core_descriptor(dest_chg, netw)?,
+                ),
+                &used,
+                cmd.backend.stop_gap(),
                 netw,
-                MemoryDatabase::default(),
-            )?);
+            )?
+        }
+        _ => Vec::new(),
+    };
 
-            let feerate = wallet.client().estimate_fee(opt.target)?;
+    let tx = &psbt.global.unsigned_tx;
+    Ok(CliOutput {
+        amount: details.sent,
+        fees,
+        address: tx
+            .output
+            .iter()
+            .filter_map(|output| Address::from_script(&output.script_pubkey, netw))
+            .map(|address| address.to_string())
+            .collect(),
+        mapping,
+        locktime: tx.lock_time,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        txid: tx.txid().to_string(),
+        psbt: Psbt {
+            base64: base64::encode(&serialize(&psbt)),
+            ur: psbt_as_ur(serialize(&psbt))?,
+        },
+    })
+}
 
-            wallet.sync(noop_progress(), Some(opt.address_gap_limit))?;
+/// Map each input of the replacement `psbt` of a sweep to the output paying the address of
+/// `dest` with the keychain and index of its source address in `source`, like the sweep does
+fn replacement_mapping(
+    psbt: &PartiallySignedTransaction,
+    source: (&str, &str),
+    dest: (&str, &str),
+    used: &HashSet<Script>,
+    stop_gap: u32,
+    netw: bdk::bitcoin::Network,
+) -> Result<Vec<Mapping>, SweepError> {
+    let offline =
+        |descriptor: &str| Wallet::new_offline(descriptor, None, netw, MemoryDatabase::default());
+    let (wallet_source, wallet_source_chg) = (offline(source.0)?, offline(source.1)?);
+    let (descriptor_destination, descriptor_destination_chg) = (offline(dest.0)?, offline(dest.1)?);
 
-            // Is user sweeping to an address or to an output descriptor?
-            let (psbt, details) = if let Some(ref addr) = opt.address {
-                let addr = if is_ur_address(addr.to_string()) {
-                    return Err(SweepError::new(
-                        "cli arg".to_string(),
-                        "UR address not implemented".to_string(),
-                    )); // TODO
-                        //decode_ur_address(opt.address)?
-                } else {
-                    Address::from_str(&addr)?
+    let tx = &psbt.global.unsigned_tx;
+    let spent = (0..tx.input.len())
+        .map(|i| {
+            spent_output(psbt, i).ok_or_else(|| {
+                SweepError::Psbt(format!(
+                    "no UTXO for input {} of the replacement",
+                    tx.input[i].previous_output
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let scripts: Vec<Script> = spent.iter().map(|u| u.script_pubkey.clone()).collect();
+    let mut source_map = DerivationMap::new(&wallet_source, &wallet_source_chg, stop_gap)?;
+    source_map.cover(&wallet_source, &wallet_source_chg, &scripts, used, stop_gap)?;
+
+    let mut mapping = Vec::new();
+    for (input, utxo) in tx.input.iter().zip(&spent) {
+        let outpoint = input.previous_output;
+        let address_source = Address::from_script(&utxo.script_pubkey, netw).ok_or_else(|| {
+            SweepError::Descriptor(format!(
+                "unsupported script of UTXO {}: {}",
+                outpoint, utxo.script_pubkey
+            ))
+        })?;
+        let (keychain, index) = source_map.get(&utxo.script_pubkey).ok_or_else(|| {
+            SweepError::GapLimit(format!(
+                "UTXO {} not found in the output descriptor. Maybe increase the address gap limit",
+                outpoint
+            ))
+        })?;
+        let address_dest = match keychain {
+            KeychainKind::External => {
+                descriptor_destination.get_address(AddressIndex::Peek(index))?
+            }
+            KeychainKind::Internal => {
+                descriptor_destination_chg.get_address(AddressIndex::Peek(index))?
+            }
+        };
+        let output = tx
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == address_dest.script_pubkey())
+            .ok_or_else(|| {
+                SweepError::Psbt(format!(
+                    "no output of the replacement pays {}, the destination of UTXO {}",
+                    address_dest.address, outpoint
+                ))
+            })?;
+        mapping.push(Mapping {
+            outpoint: outpoint.to_string(),
+            source: DerivedAddress::new(&address_source, keychain, index),
+            destination: DerivedAddress::new(&address_dest.address, keychain, index),
+            value_in: utxo.value,
+            value_out: output.value,
+        });
+    }
+    Ok(mapping)
+}
+
+/// Spend an output of an unconfirmed sweep to a descriptor with a fee lifting both to the
+/// target fee rate
+fn cpfp(cmd: &Cpfp) -> Result<CliOutput, SweepError> {
+    let netw = cmd.backend.network();
+    let txid = Txid::from_str(&cmd.txid)
+        .map_err(|e| SweepError::Cli(format!("invalid txid {}: {}", cmd.txid, e)))?;
+    let address = match cmd.address {
+        Some(ref addr) if is_ur_address(addr.to_string()) => {
+            Some(decode_ur_address(addr.to_string())?)
+        }
+        Some(ref addr) => Some(Address::from_str(addr)?),
+        None => None,
+    };
+
+    let wallet = cmd.backend.wallet(
+        &core_descriptor(&cmd.dest_descriptor, netw)?,
+        &core_descriptor(&cmd.dest_descriptor_chg, netw)?,
+    )?;
+    let rate = match cmd.fee_rate {
+        Some(rate) => rate,
+        None => cmd
+            .backend
+            .fee_rate(wallet.client(), cmd.target)?
+            .as_sat_vb(),
+    };
+    wallet.sync(noop_progress(), Some(cmd.backend.stop_gap()))?;
+
+    let details = wallet
+        .list_transactions(true)?
+        .into_iter()
+        .find(|details| details.txid == txid)
+        .ok_or_else(|| {
+            SweepError::NotReplaceable(format!(
+                "transaction {} doesn't pay to the destination descriptor, was it broadcast?",
+                txid
+            ))
+        })?;
+    if details.height.is_some() {
+        return Err(SweepError::NotReplaceable(format!(
+            "transaction {} is already confirmed",
+            txid
+        )));
+    }
+    let parent = details.transaction.ok_or_else(|| {
+        SweepError::Backend(format!("transaction {} not returned by the server", txid))
+    })?;
+
+    // the inputs of the sweep aren't in this wallet, so their values are looked up
+    let mut value_in = 0;
+    for input in &parent.input {
+        let previous = input.previous_output;
+        value_in += wallet
+            .client()
+            .get_tx(&previous.txid)?
+            .and_then(|tx| tx.output.get(previous.vout as usize).map(|o| o.value))
+            .ok_or_else(|| {
+                SweepError::Backend(format!("input {} not returned by the server", previous))
+            })?;
+    }
+    let value_out: u64 = parent.output.iter().map(|o| o.value).sum();
+    let parent_fee = value_in.saturating_sub(value_out);
+    let parent_vsize = (parent.get_weight() as u64 + 3) / 4;
+
+    let utxo = wallet
+        .list_unspent()?
+        .into_iter()
+        .filter(|u| u.outpoint.txid == txid)
+        .max_by_key(|u| u.txout.value)
+        .ok_or_else(|| {
+            SweepError::NothingToSweep(format!("no output of transaction {} left to spend", txid))
+        })?;
+    let script = match address {
+        Some(address) => address.script_pubkey(),
+        None => utxo.txout.script_pubkey.clone(),
+    };
+    // against fee sniping, like the sweep
+    let locktime = wallet.client().get_height()?;
+
+    let build = |fee: Option<u64>| -> Result<_, SweepError> {
+        let mut builder = wallet.build_tx();
+        builder
+            .manually_selected_only()
+            .add_utxo(utxo.outpoint)?
+            .set_single_recipient(script.clone())
+            .enable_rbf()
+            .nlocktime(locktime);
+        match fee {
+            Some(fee) => builder.fee_absolute(fee),
+            None => builder.fee_rate(FeeRate::from_sat_per_vb(1.0)),
+        };
+        Ok(builder.finish()?)
+    };
+    // at 1 sat/vB the fee is the size of the child
+    let (_, details) = build(None)?;
+    let fee = child_fee(parent_fee, parent_vsize, details.fees, rate)?;
+    let (psbt, details) = build(Some(fee))?;
+
+    let address = Address::from_script(&script, netw)
+        .map(|address| vec![address.to_string()])
+        .unwrap_or_default();
+    cli_output(&psbt, &details, address, Vec::new())
+}
+
+fn main() {
+    let json_errors = json_errors();
+
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if !e.use_stderr() => e.exit(), // --help and --version
+        Err(e) if json_errors => exit_with(
+            SweepError::Cli(
+                e.to_string()
+                    .trim()
+                    .trim_start_matches("error: ")
+                    .to_string(),
+            ),
+            true,
+        ),
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(SweepError::Cli(e.to_string()).exit_code());
+        }
+    };
+
+    if let Err(e) = run(cli.cmd) {
+        exit_with(e, json_errors);
+    }
+}
+
+/// Checked in the raw arguments rather than parsed, so usage errors can be reported as JSON too
+fn json_errors() -> bool {
+    std::env::args().any(|arg| arg == "--json-errors")
+}
+
+fn exit_with(e: SweepError, json_errors: bool) -> ! {
+    if json_errors {
+        eprintln!("{}", e.to_json());
+    } else {
+        eprintln!("{}", e);
+    }
+    std::process::exit(e.exit_code());
+}
+
+fn run(matches: Opt) -> Result<(), SweepError> {
+    match matches {
+        Opt::Ur(ur) => match ur.cmd {
+            UrCommand::Encode(cmd) => {
+                let out = UrOutput {
+                    ur: encode_as_ur(&cmd.input)?,
                 };
+                println!("{}", serde_json::to_string(&out)?);
+            }
+            UrCommand::Decode(cmd) => {
+                let netw = parse_network(&cmd.network);
+
+                let out = decode_ur(&cmd.ur, netw)?;
+                println!("{}", serde_json::to_string(&out)?);
+            }
+        },
+        Opt::BumpFee(mut cmd) => {
+            cmd.backend.configure(Config::load()?)?;
+            let deadline = cmd.backend.deadline();
+            let out = bump_fee(&cmd)?;
+            if cmd.interactive {
+                drop(deadline);
+                review(
+                    &[decode_psbt(&out.psbt.base64)?],
+                    cmd.backend.network(),
+                    "Print the PSBT",
+                )?;
+            }
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::Cpfp(mut cmd) => {
+            cmd.backend.configure(Config::load()?)?;
+            let deadline = cmd.backend.deadline();
+            let out = cpfp(&cmd)?;
+            if cmd.interactive {
+                drop(deadline);
+                review(
+                    &[decode_psbt(&out.psbt.base64)?],
+                    cmd.backend.network(),
+                    "Print the PSBT",
+                )?;
+            }
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::ExportUtxos(mut cmd) => {
+            cmd.backend.configure(Config::load()?)?;
+            let _deadline = cmd.backend.deadline();
+            let out = export_utxos(&cmd)?;
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::Inspect(cmd) => {
+            let netw = parse_network(&cmd.network);
 
-                dest_addresses.push(addr.to_string());
+            let psbt = decode_psbt(&cmd.psbt)?;
 
-                {
-                    // build a PSBT sweeping to an address
-                    let mut builder = wallet.build_tx();
-                    builder.drain_wallet();
-                    builder
-                        .set_single_recipient(addr.script_pubkey())
-                        .enable_rbf()
-                        .fee_rate(feerate);
-                    builder.finish()?
+            let mut descriptors = Vec::new();
+            for desc in &cmd.descriptor {
+                if is_ur_account(desc.clone()) {
+                    for (descriptor, descriptor_chg) in
+                        parse_ur_account_descriptors(desc.clone(), netw)?
+                    {
+                        descriptors.push(descriptor);
+                        descriptors.push(descriptor_chg);
+                    }
+                } else {
+                    descriptors.push(core_descriptor(desc, netw)?);
                 }
+            }
+            let descriptors = Descriptors::new(&descriptors, netw, cmd.count)?;
+
+            let out = inspect(&psbt, netw, &descriptors);
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::Policy(cmd) => {
+            let netw = parse_network(&cmd.network);
+
+            let descriptor = if is_ur_descriptor(cmd.descriptor.clone()) {
+                parse_ur_descriptor(cmd.descriptor.clone(), netw)?
             } else {
-                // build a PSBT sweeping to an output descriptor
-                // We are gonna prepare here individual wallets (descriptor, descriptor_chg, descriptor_destination,
-                // descriptor_destination_chg) so we can easily
-                // search for address indices when mapping UTXOs from a source descriptor to a
-                // destination descriptor
+                cmd.descriptor.clone()
+            };
+            let descriptor_chg = match cmd.descriptor_chg {
+                Some(ref desc) if is_ur_descriptor(desc.clone()) => {
+                    Some(parse_ur_descriptor(desc.clone(), netw)?)
+                }
+                Some(ref desc) => Some(desc.clone()),
+                None => None,
+            };
 
-                // TODO remove this when STDIN support implemented
-                let descriptor = {
-                    let desc = opt.dest_descriptor.unwrap();
-                    if is_ur_descriptor(desc.clone()) {
-                        // safe
-                        // this is UR format
-                        parse_ur_descriptor(desc)?
-                    } else {
-                        // this is bitcoin core compatible format
-                        desc
+            let wallet = Wallet::new_offline(
+                &descriptor,
+                descriptor_chg.as_ref(),
+                netw,
+                MemoryDatabase::default(),
+            )?;
+            let out = PolicyOutput {
+                external: wallet.policies(KeychainKind::External)?,
+                internal: wallet.policies(KeychainKind::Internal)?,
+            };
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::Sign(cmd) => {
+            let netw = parse_network(&cmd.network);
+
+            let mut psbt = decode_psbt(&cmd.psbt)?;
+
+            let descriptors = if is_ur_signing_key(&cmd.descriptor) {
+                // the descriptors are built from what the PSBT says about the inputs
+                let (xprv, origin) = parse_ur_signing_key(&cmd.descriptor, netw)?;
+                signing_descriptors(&xprv, &origin, &psbt)?
+                    .into_iter()
+                    .map(|desc| (desc, None))
+                    .collect()
+            } else {
+                let descriptor = if is_ur_descriptor(cmd.descriptor.clone()) {
+                    parse_ur_descriptor(cmd.descriptor.clone(), netw)?
+                } else {
+                    cmd.descriptor.clone()
+                };
+                let descriptor_chg = match cmd.descriptor_chg {
+                    Some(ref desc) if is_ur_descriptor(desc.clone()) => {
+                        Some(parse_ur_descriptor(desc.clone(), netw)?)
                     }
+                    Some(ref desc) => Some(desc.clone()),
+                    None => None,
                 };
+                vec![(descriptor, descriptor_chg)]
+            };
 
-                // TODO remove this when STDIN support implemented
-                let descriptor_chg = {
-                    let desc = opt.dest_descriptor_chg.unwrap();
-                    if is_ur_descriptor(desc.to_string()) {
-                        // this is UR format
-                        parse_ur_descriptor(desc)?
+            for outpoint in missing_utxos(&psbt) {
+                eprintln!(
+                    "warning: the PSBT has no UTXO for the input {}, its value can't be checked",
+                    outpoint
+                );
+            }
+            if !cmd.expect_dest.is_empty() {
+                let mut addresses = Vec::new();
+                let mut expected = Vec::new();
+                for dest in &cmd.expect_dest {
+                    if is_ur_address(dest.clone()) {
+                        addresses.push(decode_ur_address(dest.clone())?.script_pubkey());
+                    } else if let Ok(addr) = Address::from_str(dest) {
+                        addresses.push(addr.script_pubkey());
                     } else {
-                        // this is bitcoin core compatible format
-                        desc
+                        expected.push(core_descriptor(dest, netw)?);
                     }
-                };
+                }
+                let descriptors = Descriptors::new(&expected, netw, cmd.count)?;
+                check_destinations(&psbt, &addresses, &descriptors, netw)?;
+            }
+            check_fee(&psbt, cmd.max_fee, cmd.max_fee_rate)?;
 
-                // user is sweeping to an output descriptor
-                let descriptor_destination = Rc::new(Wallet::new_offline(
-                    &descriptor,
-                    None,
-                    netw,
-                    MemoryDatabase::default(),
-                )?);
+            if cmd.interactive {
+                review(&[psbt.clone()], netw, "Sign the PSBT")?;
+            }
 
-                let descriptor_destination_chg = Rc::new(Wallet::new_offline(
-                    &descriptor_chg,
-                    None,
+            for (descriptor, descriptor_chg) in &descriptors {
+                let wallet = Wallet::new_offline(
+                    descriptor,
+                    descriptor_chg.as_ref(),
                     netw,
                     MemoryDatabase::default(),
-                )?);
+                )?;
 
-                fn get_child_indx<D: bdk::database::BatchDatabase, B>(
-                    w: Rc<Wallet<B, D>>,
-                    utxo: bdk::LocalUtxo,
-                    network: bdk::bitcoin::Network,
-                    address_gap_limit: u32,
-                ) -> Option<u32> {
-                    for i in 0..address_gap_limit {
-                        let addr = w.get_address(bdk::wallet::AddressIndex::Peek(i)).unwrap();
-                        let address =
-                            Address::from_script(&utxo.txout.script_pubkey, network).unwrap(); // TODO
-                        if addr.address == address {
-                            return Some(i);
-                        }
-                    }
-                    None
-                }
+                let _finalized = wallet.sign(&mut psbt, SignOptions::default())?;
+            }
 
-                let unspent = wallet.list_unspent().unwrap();
-
-                {
-                    // here we construct a psbt with zero fees so we can determine Tx size
-                    // Based on Tx size we can construct a rael psbt with real fees in the next stage
-                    let mut builder = wallet.build_tx();
-                    for u in &unspent {
-                        let indx = get_child_indx(
-                            Rc::clone(&wallet_source),
-                            u.clone(),
-                            netw,
-                            opt.address_gap_limit,
-                        );
-                        let indx_chg = get_child_indx(
-                            Rc::clone(&wallet_source_chg),
-                            u.clone(),
-                            netw,
-                            opt.address_gap_limit,
-                        );
-                        let address_dest = if let Some(d) = indx {
-                            descriptor_destination
-                                .get_address(bdk::wallet::AddressIndex::Peek(d))?
-                        } else if let Some(d) = indx_chg {
-                            descriptor_destination_chg
-                                .get_address(bdk::wallet::AddressIndex::Peek(d))?
-                        } else {
-                            return Err(SweepError::new(
-                        "bip32 index".to_string(),
-                        "Address not found in output descriptor. Maybe increase the address gap"
-                            .to_string(),
-                    ));
-                        };
+            let out = Psbt {
+                base64: base64::encode(&serialize(&psbt)),
+                ur: psbt_as_ur(serialize(&psbt))?,
+            };
 
-                        dest_addresses.push(address_dest.to_string());
+            println!("{}", serde_json::to_string(&out)?);
+        }
+        Opt::Sweep(mut opt) => {
+            opt.backend.configure(Config::load()?)?;
+            let deadline = opt.backend.deadline();
+            let netw = opt.backend.network();
 
-                        builder
-                            .manually_selected_only()
-                            .add_utxo(u.outpoint)
-                            .unwrap()
-                            .ordering(tx_builder::TxOrdering::Untouched)
-                            .add_recipient(address_dest.script_pubkey(), u.txout.value)
-                            .enable_rbf();
+            // A crypto-account expands to every descriptor it contains and the change
+            // descriptors are derived from them
+            let sources = if is_ur_account(opt.descriptor.clone()) {
+                parse_ur_account_descriptors(opt.descriptor.clone(), netw)?
+            } else {
+                // TODO remove this when STDIN support implemented
+                let descriptor = Some(opt.descriptor.clone());
+                let descriptor = if let Some(ref desc) = descriptor {
+                    if is_ur_descriptor(desc.to_string()) {
+                        // this is UR format
+                        parse_ur_descriptor(desc.to_string(), netw)?
+                    } else {
+                        // this is bitcoin core compatible format
+                        desc.to_string()
                     }
-                    builder.fee_rate(feerate);
-                    let err = builder.finish();
-
-                    let fee_per_utxo = if let Err(e) = err {
-                        let err_str = e.to_string();
-
-                        let split = err_str.split(',');
-                        let vec = split.collect::<Vec<&str>>();
-
-                        let needed = parse_int(&vec[0]).unwrap();
-                        let available = parse_int(&vec[1]).unwrap();
+                } else {
+                    return Err(SweepError::Cli(
+                        "UR descriptor cannot be currently passed via STDIN. Pass it as a CLI arg"
+                            .to_string(),
+                    ));
+                };
 
-                        (needed - available) as u64 / unspent.len() as u64
+                let descriptor_chg = if let Some(ref desc) = opt.descriptor_chg {
+                    if is_ur_descriptor(desc.to_string()) {
+                        // this is UR format
+                        parse_ur_descriptor(desc.to_string(), netw)?
                     } else {
-                        panic!("fees error");
-                    };
-
-                    // Now  we can construct a PSBT with real fees:
-                    let mut builder = wallet.build_tx();
-                    let mut fee_combined = 0;
-                    for u in &unspent {
-                        let indx = get_child_indx(
-                            Rc::clone(&wallet_source),
-                            u.clone(),
-                            netw,
-                            opt.address_gap_limit,
-                        );
-                        let indx_chg = get_child_indx(
-                            Rc::clone(&wallet_source_chg),
-                            u.clone(),
-                            netw,
-                            opt.address_gap_limit,
-                        );
-                        let address_dest = if let Some(d) = indx {
-                            descriptor_destination
-                                .get_address(bdk::wallet::AddressIndex::Peek(d))?
-                        } else if let Some(d) = indx_chg {
-                            descriptor_destination_chg
-                                .get_address(bdk::wallet::AddressIndex::Peek(d))?
-                        } else {
-                            return Err(SweepError::new(
-                        "bip32 index".to_string(),
-                        "Address not found in output descriptor. Maybe increase the address gap limit"
+                        // this is bitcoin core compatible format
+                        desc.to_string()
+                    }
+                } else {
+                    return Err(SweepError::Cli(
+                        "Change descriptor (-c) is required unless sweeping a crypto-account"
                             .to_string(),
                     ));
-                        };
-
-                        dest_addresses.push(address_dest.to_string());
-
-                        let recipient_amount = if u.txout.value > fee_per_utxo {
-                            fee_combined += fee_per_utxo;
-                            u.txout.value - fee_per_utxo
-                        } else {
-                            fee_combined += u.txout.value;
-                            0
-                        };
-
-                        builder
-                            .manually_selected_only()
-                            .add_utxo(u.outpoint)
-                            .unwrap()
-                            .ordering(tx_builder::TxOrdering::Untouched)
-                            .add_recipient(address_dest.script_pubkey(), recipient_amount)
-                            .enable_rbf();
-                    }
-                    builder.fee_absolute(fee_combined);
-                    builder.finish()?
-                }
-            };
+                };
 
-            /*
-                println!(
-                    "DEBUG psbt: {}",
-                    serde_json::to_string_pretty(&psbt).unwrap()
-                );
-            */
-
-            let out = CliOutput {
-                amount: details.sent,
-                fees: details.fees,
-                address: dest_addresses,
-                timestamp: details.timestamp,
-                txid: details.txid.to_string(),
-                psbt: Psbt {
-                    base64: base64::encode(&serialize(&psbt)),
-                    ur: psbt_as_ur(serialize(&psbt))?,
-                },
+                vec![(descriptor, descriptor_chg)]
             };
 
-            println!("{}", serde_json::to_string(&out)?);
+            // every account is swept with its own PSBT(s). Accounts without funds are skipped
+            let mut outs = Vec::new();
+            for (descriptor, descriptor_chg) in &sources {
+                outs.extend(sweep(&opt, descriptor, descriptor_chg)?);
+            }
+            if opt.interactive && !outs.is_empty() {
+                drop(deadline);
+                let psbts = outs
+                    .iter()
+                    .map(|out| decode_psbt(&out.psbt.base64))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let action = if psbts.len() == 1 {
+                    "Print the PSBT"
+                } else {
+                    "Print the PSBTs"
+                };
+                review(&psbts, netw, action)?;
+            }
+
+            if sources.len() == 1 && opt.privacy.is_none() {
+                match outs.pop() {
+                    Some(out) => println!("{}", serde_json::to_string(&out)?),
+                    None if opt.allow_empty => println!("{{}}"),
+                    None => return Err(SweepError::NothingToSweep("Nothing to sweep".to_string())),
+                }
+            } else {
+                if outs.is_empty() && !opt.allow_empty {
+                    return Err(SweepError::NothingToSweep(if sources.len() == 1 {
+                        "Nothing to sweep".to_string()
+                    } else {
+                        "Nothing to sweep in any of the account descriptors".to_string()
+                    }));
+                }
+                println!("{}", serde_json::to_string(&outs)?);
+            }
         }
     }
 
@@ -0,0 +1,286 @@
+use bdk::bitcoin::{OutPoint, Script, Transaction, Txid};
+use bdk::blockchain::{noop_progress, Blockchain, Capability, Progress};
+use bdk::database::{BatchDatabase, BatchOperations, Database, MemoryDatabase};
+use bdk::{Error, FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+use std::collections::HashSet;
+
+use crate::backend::Backend;
+
+/// Several Electrum or Esplora servers. Without a quorum the first one answering is used and
+/// the others are tried in order on errors. With a quorum all of them are queried in parallel
+/// and that many have to return the same UTXOs, so a single server can't hide any, and fee
+/// rates and heights are the median of the answers, so a minority can't inflate them.
+pub struct MultiBlockchain {
+    /// name and backend of each server
+    servers: Vec<(String, Backend)>,
+    quorum: usize,
+}
+
+impl MultiBlockchain {
+    pub fn new(servers: Vec<(String, Backend)>, quorum: usize) -> Self {
+        MultiBlockchain { servers, quorum }
+    }
+
+    /// Answer of the first server without an error
+    fn failover<T, F>(&self, query: F) -> Result<T, Error>
+    where
+        F: Fn(&Backend) -> Result<T, Error>,
+    {
+        let mut errors = Vec::new();
+        for (name, server) in &self.servers {
+            match query(server) {
+                Ok(answer) => return Ok(answer),
+                Err(e) => errors.push(format!("{}: {}", name, e)),
+            }
+        }
+        Err(Error::Generic(format!(
+            "all servers failed: {}",
+            errors.join(", ")
+        )))
+    }
+
+    /// Answers of all servers queried in parallel, at least `quorum` of them
+    fn query_all<T, F>(&self, query: F) -> Result<Vec<T>, Error>
+    where
+        T: Send,
+        F: Fn(&Backend) -> Result<T, Error> + Sync,
+    {
+        let results: Vec<Result<T, String>> = crossbeam_utils::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .servers
+                .iter()
+                .map(|(name, server)| {
+                    let query = &query;
+                    scope.spawn(move |_| query(server).map_err(|e| format!("{}: {}", name, e)))
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err("query panicked".to_string()))
+                })
+                .collect()
+        })
+        .map_err(|_| Error::Generic("query panicked".to_string()))?;
+
+        let mut answers = Vec::new();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(answer) => answers.push(answer),
+                Err(e) => errors.push(e),
+            }
+        }
+        if answers.len() < self.quorum {
+            return Err(Error::Generic(format!(
+                "{} of {} servers answered, {} required: {}",
+                answers.len(),
+                self.servers.len(),
+                self.quorum,
+                errors.join(", ")
+            )));
+        }
+        Ok(answers)
+    }
+}
+
+impl Blockchain for MultiBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        // what all servers can do
+        let mut servers = self
+            .servers
+            .iter()
+            .map(|(_, server)| server.get_capabilities());
+        let first = servers.next().unwrap_or_default();
+        servers.fold(first, |all, capabilities| {
+            all.intersection(&capabilities).cloned().collect()
+        })
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), Error> {
+        // every server syncs a database of its own, a failed sync may have stored part of
+        // the history
+        let scripts = cached_scripts(database)?;
+        let sync = |server: &Backend| -> Result<History, Error> {
+            let mut synced = MemoryDatabase::default();
+            for (script, keychain, index) in &scripts {
+                synced.set_script_pubkey(script, *keychain, *index)?;
+            }
+            server.setup(stop_gap, &mut synced, noop_progress())?;
+            History::new(&synced)
+        };
+        if self.quorum <= 1 {
+            return self.failover(sync)?.store(database);
+        }
+        let synced = self.query_all(sync)?;
+
+        let sets: Vec<Vec<(OutPoint, u64)>> = synced.iter().map(History::utxo_set).collect();
+        match agreeing(&sets, self.quorum) {
+            Ok(i) => synced[i].store(database),
+            Err(most) => Err(Error::Generic(format!(
+                "the servers disagree on the UTXOs: at most {} of {} return the same, {} required",
+                most,
+                self.servers.len(),
+                self.quorum
+            ))),
+        }
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        // a transaction is checked against its txid, one server is enough
+        self.failover(|server| match server.get_tx(txid)? {
+            Some(tx) if tx.txid() != *txid => Err(Error::Generic(format!(
+                "returned {} for transaction {}",
+                tx.txid(),
+                txid
+            ))),
+            tx => Ok(tx),
+        })
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
+        // to every server, any of them relaying it will do
+        let mut errors = Vec::new();
+        for (name, server) in &self.servers {
+            if let Err(e) = server.broadcast(tx) {
+                errors.push(format!("{}: {}", name, e));
+            }
+        }
+        if errors.len() == self.servers.len() {
+            return Err(Error::Generic(format!(
+                "all servers failed: {}",
+                errors.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        if self.quorum <= 1 {
+            return self.failover(|server| server.get_height());
+        }
+        Ok(median(self.query_all(|server| server.get_height())?))
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        if self.quorum <= 1 {
+            return self.failover(|server| server.estimate_fee(target));
+        }
+        let rates = self.query_all(|server| server.estimate_fee(target).map(|r| r.as_sat_vb()))?;
+        Ok(FeeRate::from_sat_per_vb(median(rates)))
+    }
+}
+
+/// Index of a UTXO set returned by at least `quorum` servers, or else the largest number of
+/// servers returning the same
+fn agreeing(sets: &[Vec<(OutPoint, u64)>], quorum: usize) -> Result<usize, usize> {
+    let counts: Vec<usize> = sets
+        .iter()
+        .map(|set| sets.iter().filter(|other| *other == set).count())
+        .collect();
+    counts
+        .iter()
+        .position(|count| *count >= quorum)
+        .ok_or_else(|| counts.iter().copied().max().unwrap_or(0))
+}
+
+/// Lower median of the answers, of which there is at least one
+fn median<T: PartialOrd + Copy>(mut values: Vec<T>) -> T {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values[(values.len() - 1) / 2]
+}
+
+/// Scripts the wallet caches in `database` before a sync, with their keychain and index
+fn cached_scripts<D: BatchDatabase>(
+    database: &D,
+) -> Result<Vec<(Script, KeychainKind, u32)>, Error> {
+    let mut scripts = Vec::new();
+    for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+        let mut index = 0;
+        while let Some(script) = database.get_script_pubkey_from_path(*keychain, index)? {
+            scripts.push((script, *keychain, index));
+            index += 1;
+        }
+    }
+    Ok(scripts)
+}
+
+/// What a server returned for the scripts of a wallet. A database of its own would do, but
+/// it can't be returned from the thread syncing it.
+struct History {
+    utxos: Vec<LocalUtxo>,
+    txs: Vec<TransactionDetails>,
+    last_index: Vec<(KeychainKind, u32)>,
+}
+
+impl History {
+    fn new(database: &MemoryDatabase) -> Result<Self, Error> {
+        let mut last_index = Vec::new();
+        for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+            if let Some(index) = database.get_last_index(*keychain)? {
+                last_index.push((*keychain, index));
+            }
+        }
+        Ok(History {
+            utxos: database.iter_utxos()?,
+            txs: database.iter_txs(true)?,
+            last_index,
+        })
+    }
+
+    /// UTXOs with their values, sorted for comparison
+    fn utxo_set(&self) -> Vec<(OutPoint, u64)> {
+        let mut set: Vec<(OutPoint, u64)> = self
+            .utxos
+            .iter()
+            .map(|u| (u.outpoint, u.txout.value))
+            .collect();
+        set.sort();
+        set
+    }
+
+    fn store<D: BatchDatabase>(&self, database: &mut D) -> Result<(), Error> {
+        for utxo in &self.utxos {
+            database.set_utxo(utxo)?;
+        }
+        for tx in &self.txs {
+            database.set_tx(tx)?;
+        }
+        for (keychain, index) in &self.last_index {
+            database.set_last_index(*keychain, *index)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn quorum_test() {
+    use std::str::FromStr;
+
+    let outpoint = |vout| {
+        OutPoint::from_str(&format!(
+            "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:{}",
+            vout
+        ))
+        .unwrap()
+    };
+    let all = vec![(outpoint(0), 10_000), (outpoint(1), 20_000)];
+    // a server hiding a UTXO
+    let hiding = vec![(outpoint(0), 10_000)];
+
+    let sets = vec![hiding.clone(), all.clone(), all.clone()];
+    assert_eq!(agreeing(&sets, 2), Ok(1));
+    assert_eq!(agreeing(&sets, 3), Err(2));
+    assert_eq!(agreeing(&[hiding, all], 2), Err(1));
+
+    // an inflated fee rate doesn't move the median
+    assert_eq!(median(vec![5.0, 500.0, 4.0]), 5.0);
+    assert_eq!(median(vec![700_000, 700_001]), 700_000);
+}
@@ -0,0 +1,258 @@
+use bdk::bitcoin::consensus::{deserialize, serialize};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Network, OutPoint, Script, Transaction, TxOut, Txid};
+use bdk::blockchain::{Blockchain, Capability, Progress};
+use bdk::database::{BatchDatabase, BatchOperations, Database};
+use bdk::{Error, FeeRate, LocalUtxo};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::errors::SweepError;
+
+/// UTXOs of a descriptor pair as exported by `export-utxos` on an online machine and read by
+/// `sweep --utxos-file` on an offline one
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UtxoSet {
+    /// network of the UTXOs, as in bitcoin::Network
+    pub network: String,
+    /// block height at the export, the locktime of the sweep against fee sniping
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    pub utxos: Vec<UtxoEntry>,
+}
+
+/// A UTXO with the transaction creating it, or just the output for segwit scripts
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UtxoEntry {
+    /// txid:vout
+    pub outpoint: String,
+    /// transaction creating the UTXO, hex encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub witness_utxo: Option<WitnessUtxo>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WitnessUtxo {
+    pub value: u64,
+    /// hex encoded
+    pub script_pubkey: String,
+}
+
+impl UtxoSet {
+    pub fn load(path: &Path) -> Result<Self, SweepError> {
+        let file = std::fs::read_to_string(path)
+            .map_err(|e| SweepError::Cli(format!("unable to read {}: {}", path.display(), e)))?;
+        serde_json::from_str(&file)
+            .map_err(|e| SweepError::Cli(format!("invalid UTXOs file {}: {}", path.display(), e)))
+    }
+
+    pub fn new(network: Network, height: u32) -> Self {
+        UtxoSet {
+            network: network.to_string(),
+            height: Some(height),
+            utxos: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, outpoint: OutPoint, tx: &Transaction) {
+        self.utxos.push(UtxoEntry {
+            outpoint: outpoint.to_string(),
+            tx: Some(hex::encode(serialize(tx))),
+            witness_utxo: None,
+        });
+    }
+}
+
+/// Blockchain of an offline sweep: the sync stores the UTXOs of a `UtxoSet` derived from the
+/// descriptors. Others are skipped like a sync would, e.g. those of another descriptor of a
+/// crypto-account.
+pub struct OfflineBlockchain {
+    utxos: Vec<(OutPoint, TxOut)>,
+    txs: Vec<Transaction>,
+    height: u32,
+}
+
+impl OfflineBlockchain {
+    pub fn new(set: &UtxoSet, network: Network) -> Result<Self, SweepError> {
+        if set.network != network.to_string() {
+            return Err(SweepError::Cli(format!(
+                "the UTXOs file is for {}, not for {}",
+                set.network, network
+            )));
+        }
+
+        let mut utxos = Vec::new();
+        let mut txs: Vec<Transaction> = Vec::new();
+        for entry in &set.utxos {
+            let outpoint = OutPoint::from_str(&entry.outpoint).map_err(|e| {
+                SweepError::Cli(format!("invalid outpoint {}: {}", entry.outpoint, e))
+            })?;
+            let txout = match (&entry.tx, &entry.witness_utxo) {
+                (Some(tx), _) => {
+                    let tx: Transaction = deserialize(&decode_hex(tx)?)?;
+                    if tx.txid() != outpoint.txid {
+                        return Err(SweepError::Cli(format!(
+                            "the transaction of UTXO {} is {}",
+                            outpoint,
+                            tx.txid()
+                        )));
+                    }
+                    let txout =
+                        tx.output
+                            .get(outpoint.vout as usize)
+                            .cloned()
+                            .ok_or_else(|| {
+                                SweepError::Cli(format!(
+                                    "transaction {} has no output {}",
+                                    outpoint.txid, outpoint.vout
+                                ))
+                            })?;
+                    txs.push(tx);
+                    txout
+                }
+                (None, Some(witness_utxo)) => TxOut {
+                    value: witness_utxo.value,
+                    script_pubkey: Script::from(decode_hex(&witness_utxo.script_pubkey)?),
+                },
+                (None, None) => {
+                    return Err(SweepError::Cli(format!(
+                        "UTXO {} has neither a tx nor a witness_utxo",
+                        outpoint
+                    )))
+                }
+            };
+            utxos.push((outpoint, txout));
+        }
+
+        Ok(OfflineBlockchain {
+            utxos,
+            txs,
+            height: set.height.unwrap_or(0),
+        })
+    }
+
+    /// Add the witness UTXOs bdk leaves out without the transactions creating them
+    pub fn complete_psbt(&self, psbt: &mut PartiallySignedTransaction) {
+        let inputs = psbt
+            .global
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(psbt.inputs.iter_mut());
+        for (txin, input) in inputs {
+            if input.witness_utxo.is_some() || input.non_witness_utxo.is_some() {
+                continue;
+            }
+            input.witness_utxo = self
+                .utxos
+                .iter()
+                .find(|(outpoint, _)| *outpoint == txin.previous_output)
+                .map(|(_, txout)| txout.clone());
+        }
+    }
+}
+
+impl Blockchain for OfflineBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        HashSet::new()
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        _stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), Error> {
+        for (outpoint, txout) in &self.utxos {
+            let (keychain, _) = match database.get_path_from_script_pubkey(&txout.script_pubkey)? {
+                Some(path) => path,
+                None => continue,
+            };
+            database.set_utxo(&LocalUtxo {
+                outpoint: *outpoint,
+                txout: txout.clone(),
+                keychain,
+            })?;
+        }
+        for tx in &self.txs {
+            database.set_raw_tx(tx)?;
+        }
+        Ok(())
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        Ok(self.txs.iter().find(|tx| tx.txid() == *txid).cloned())
+    }
+
+    fn broadcast(&self, _tx: &Transaction) -> Result<(), Error> {
+        Err(Error::Generic(
+            "an offline sweep can't be broadcast".to_string(),
+        ))
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        Ok(self.height)
+    }
+
+    fn estimate_fee(&self, _target: usize) -> Result<FeeRate, Error> {
+        Err(Error::Generic(
+            "fees can't be estimated offline, pass --fee-rate".to_string(),
+        ))
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, SweepError> {
+    hex::decode(hex).map_err(|e| SweepError::Cli(format!("invalid hex {}: {}", hex, e)))
+}
+
+#[test]
+fn utxo_set_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::{Address, TxIn};
+
+    let script = Address::from_str("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")
+        .unwrap()
+        .script_pubkey();
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn::default()],
+        output: vec![TxOut {
+            value: 10_000,
+            script_pubkey: script.clone(),
+        }],
+    };
+
+    let mut set = UtxoSet::new(Network::Regtest, 150);
+    set.push(OutPoint::new(tx.txid(), 0), &tx);
+    set.utxos.push(UtxoEntry {
+        outpoint: format!("{}:1", tx.txid()),
+        tx: None,
+        witness_utxo: Some(WitnessUtxo {
+            value: 20_000,
+            script_pubkey: format!("{:x}", script),
+        }),
+    });
+    let set: UtxoSet = serde_json::from_str(&serde_json::to_string(&set)?)?;
+
+    let blockchain = OfflineBlockchain::new(&set, Network::Regtest)?;
+    assert_eq!(blockchain.get_height().unwrap(), 150);
+    assert_eq!(blockchain.get_tx(&tx.txid()).unwrap(), Some(tx.clone()));
+    assert_eq!(blockchain.utxos[1].1.value, 20_000);
+    assert!(blockchain.estimate_fee(6).is_err());
+
+    assert!(OfflineBlockchain::new(&set, Network::Testnet).is_err());
+
+    // the transaction has no output 3, and a UTXO needs one of tx and witness_utxo
+    let mut set = set;
+    set.utxos.truncate(1);
+    set.utxos[0].outpoint = format!("{}:3", tx.txid());
+    assert!(OfflineBlockchain::new(&set, Network::Regtest).is_err());
+    set.utxos[0].tx = None;
+    assert!(OfflineBlockchain::new(&set, Network::Regtest).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use bdk::bitcoin::consensus::serialize;
+use bdk::bitcoin::secp256k1::{Secp256k1, Signature};
+use bdk::bitcoin::util::bip32::ChildNumber;
+use bdk::bitcoin::{PublicKey, Script, SigHashType, Transaction, TxIn, TxOut};
+use bdk::database::BatchDatabase;
+use bdk::descriptor::policy::Condition;
+use bdk::miniscript::descriptor::DescriptorPublicKey;
+use bdk::miniscript::{BitcoinSig, Descriptor, MiniscriptKey, Satisfier, TranslatePk2};
+use bdk::{FeeRate, KeychainKind, Wallet};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::errors::SweepError;
+
+/// Policy path as passed to `--policy-path`: ids of the policy nodes (see `sweeptool policy`)
+/// mapped to the indexes of the items selected
+pub type PolicyPath = BTreeMap<String, Vec<usize>>;
+
+pub fn parse_policy_path(path: &str) -> Result<PolicyPath, SweepError> {
+    serde_json::from_str(path).map_err(|e| {
+        SweepError::Cli(format!(
+            "invalid policy path {}, expected e.g. {{\"<policy id>\": [0, 2]}}: {}",
+            path, e
+        ))
+    })
+}
+
+/// Spending plan of the descriptors of a wallet along a policy path. bdk estimates fees with
+/// the largest satisfaction of a descriptor, the plan with the satisfaction of the path.
+pub struct Plan {
+    /// satisfaction weight of an input of the external and the internal keychain
+    weights: HashMap<KeychainKind, usize>,
+}
+
+impl Plan {
+    pub fn new<B, D: BatchDatabase>(
+        wallet: &Wallet<B, D>,
+        path: &PolicyPath,
+    ) -> Result<Self, SweepError> {
+        let mut weights = HashMap::new();
+        for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+            let (descriptor, policy) = match (
+                wallet.public_descriptor(*keychain)?,
+                wallet.policies(*keychain)?,
+            ) {
+                (Some(descriptor), Some(policy)) => (descriptor, policy),
+                _ => continue,
+            };
+            let condition = policy.get_condition(path)?;
+            let mut keys = HashSet::new();
+            path_keys(&serde_json::to_value(&policy)?, path, &mut keys)?;
+            weights.insert(
+                *keychain,
+                satisfaction_weight(&descriptor, &keys, condition)?,
+            );
+        }
+        Ok(Plan { weights })
+    }
+
+    /// Fee at `feerate` of a transaction spending inputs of `keychains` along the path to
+    /// `outputs`
+    pub fn fee(&self, keychains: &[KeychainKind], outputs: &[Script], feerate: FeeRate) -> u64 {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default(); keychains.len()],
+            output: outputs
+                .iter()
+                .map(|script| TxOut {
+                    value: 0,
+                    script_pubkey: script.clone(),
+                })
+                .collect(),
+        };
+        let witness: usize = keychains
+            .iter()
+            .map(|k| self.weights.get(k).copied().unwrap_or(0))
+            .sum();
+        // segwit marker and flag
+        let weight = tx.get_weight() + 2 + witness;
+        let vbytes = (weight + 3) / 4;
+        (feerate.as_sat_vb() * vbytes as f32).ceil() as u64
+    }
+}
+
+/// Keys signing along `path`: fingerprints of extended keys and single public keys, as bdk
+/// shows them in a policy
+fn path_keys(
+    policy: &Value,
+    path: &PolicyPath,
+    keys: &mut HashSet<String>,
+) -> Result<(), SweepError> {
+    let id = policy["id"].as_str().unwrap_or_default();
+    let threshold = policy["threshold"].as_u64().unwrap_or_default() as usize;
+    match policy["type"].as_str() {
+        Some("SIGNATURE") | Some("SIGNATUREKEY") => {
+            keys.insert(key_id(policy));
+        }
+        Some("MULTISIG") => {
+            let all = policy["keys"].as_array().cloned().unwrap_or_default();
+            // any of the keys will do for the weight
+            let selected = path
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| (0..threshold).collect());
+            for i in selected {
+                let key = all
+                    .get(i)
+                    .ok_or_else(|| SweepError::Cli(format!("policy {} has no key {}", id, i)))?;
+                keys.insert(key_id(key));
+            }
+        }
+        Some("THRESH") => {
+            let items = policy["items"].as_array().cloned().unwrap_or_default();
+            let selected = match path.get(id) {
+                Some(selected) => selected.clone(),
+                None if threshold == items.len() => (0..threshold).collect(),
+                None => {
+                    return Err(SweepError::Cli(format!(
+                        "select {} of the {} items of policy {} with --policy-path",
+                        threshold,
+                        items.len(),
+                        id
+                    )))
+                }
+            };
+            for i in selected {
+                let item = items
+                    .get(i)
+                    .ok_or_else(|| SweepError::Cli(format!("policy {} has no item {}", id, i)))?;
+                path_keys(item, path, keys)?;
+            }
+        }
+        // timelocks and hashes don't sign
+        _ => {}
+    }
+    Ok(())
+}
+
+fn key_id(key: &Value) -> String {
+    key["fingerprint"]
+        .as_str()
+        .or_else(|| key["pubkey"].as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn descriptor_key_id(key: &DescriptorPublicKey) -> String {
+    match key {
+        DescriptorPublicKey::SinglePub(single) => single.key.to_string(),
+        DescriptorPublicKey::XPub(_) => key.master_fingerprint().to_string(),
+    }
+}
+
+/// Satisfier with a dummy signature of the largest size for the keys of the path, which
+/// also satisfies the timelocks of the path only
+struct PathSatisfier {
+    keys: HashSet<PublicKey>,
+    condition: Condition,
+}
+
+impl PathSatisfier {
+    fn signature() -> BitcoinSig {
+        // both r and s have the high bit set, so the DER encoding is 72 bytes
+        let signature = Signature::from_compact(&[0x80; 64]).expect("valid signature");
+        (signature, SigHashType::All)
+    }
+}
+
+impl Satisfier<PublicKey> for PathSatisfier {
+    fn lookup_sig(&self, key: &PublicKey) -> Option<BitcoinSig> {
+        self.keys.get(key).map(|_| Self::signature())
+    }
+
+    fn lookup_pkh_pk(&self, hash: &<PublicKey as MiniscriptKey>::Hash) -> Option<PublicKey> {
+        self.keys
+            .iter()
+            .find(|key| key.to_pubkeyhash() == *hash)
+            .copied()
+    }
+
+    fn lookup_pkh_sig(
+        &self,
+        hash: &<PublicKey as MiniscriptKey>::Hash,
+    ) -> Option<(PublicKey, BitcoinSig)> {
+        self.lookup_pkh_pk(hash).map(|key| (key, Self::signature()))
+    }
+
+    fn check_older(&self, sequence: u32) -> bool {
+        self.condition.csv.map_or(false, |csv| sequence <= csv)
+    }
+
+    fn check_after(&self, locktime: u32) -> bool {
+        self.condition
+            .timelock
+            .map_or(false, |timelock| locktime <= timelock)
+    }
+}
+
+/// Weight of the script sig and the witness of an input of `descriptor` signed by `keys`.
+/// Keys and signatures have the same size at every index, so the first address stands for all.
+fn satisfaction_weight(
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    keys: &HashSet<String>,
+    condition: Condition,
+) -> Result<usize, SweepError> {
+    let secp = Secp256k1::verification_only();
+    let path_keys = RefCell::new(HashSet::new());
+    let derived: Descriptor<PublicKey> = descriptor
+        .derive(ChildNumber::from_normal_idx(0)?)
+        .translate_pk2(|key: &DescriptorPublicKey| {
+            let public = key.derive_public_key(&secp).map_err(|e| {
+                SweepError::Descriptor(format!("unable to derive {}: {:?}", key, e))
+            })?;
+            if keys.contains(&descriptor_key_id(key)) {
+                path_keys.borrow_mut().insert(public);
+            }
+            Ok::<_, SweepError>(public)
+        })?;
+
+    let satisfier = PathSatisfier {
+        keys: path_keys.into_inner(),
+        condition,
+    };
+    let mut txin = TxIn::default();
+    derived.satisfy(&mut txin, &satisfier).map_err(|e| {
+        SweepError::Descriptor(format!(
+            "the policy path doesn't satisfy {}: {}",
+            descriptor, e
+        ))
+    })?;
+
+    Ok(txin.script_sig.len() * 4 + serialize(&txin.witness).len())
+}
+
+#[test]
+fn plan_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::Network;
+    use bdk::database::MemoryDatabase;
+    use std::str::FromStr;
+
+    // spent by A and B, or after block 800000 by A alone
+    let desc = "wsh(thresh(2,pk([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),s:pk([8a6d2a0c/84h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/0/*),sln:after(800000)))";
+    let wallet = Wallet::new_offline(desc, None, Network::Testnet, MemoryDatabase::default())?;
+    let policy = wallet.policies(KeychainKind::External)?.unwrap();
+
+    let both: PolicyPath = vec![(policy.id.clone(), vec![0, 1])].into_iter().collect();
+    let recovery: PolicyPath = vec![(policy.id.clone(), vec![0, 2])].into_iter().collect();
+
+    let both = Plan::new(&wallet, &both)?;
+    let recovery = Plan::new(&wallet, &recovery)?;
+    // one signature less
+    assert!(recovery.weights[&KeychainKind::External] < both.weights[&KeychainKind::External]);
+
+    let output = bdk::bitcoin::Address::from_str("tb1qvctwrh8ckrex8daxya4xleaevcp299ttd9gnev")
+        .unwrap()
+        .script_pubkey();
+    let feerate = FeeRate::from_sat_per_vb(1.0);
+    assert!(
+        recovery.fee(&[KeychainKind::External], &[output.clone()], feerate)
+            < both.fee(&[KeychainKind::External], &[output], feerate)
+    );
+
+    // the thresh needs a selection
+    assert!(Plan::new(&wallet, &PolicyPath::new()).is_err());
+
+    assert!(parse_policy_path(r#"{"abc": [0, 2]}"#).is_ok());
+    assert!(parse_policy_path("abc").is_err());
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use bdk::bitcoin::Script;
+use bdk::{FeeRate, LocalUtxo};
+use rand::Rng;
+
+/// Split the UTXOs into the groups swept with a PSBT of their own: every UTXO alone
+/// (`utxo`), or all UTXOs of the same address together (`address`), which are linked
+/// on-chain anyway. Returns indexes into `unspent`, in the order of first appearance.
+pub fn privacy_groups(unspent: &[LocalUtxo], mode: &str) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (i, u) in unspent.iter().enumerate() {
+        let cluster = if mode == "address" {
+            groups
+                .iter()
+                .position(|g| unspent[g[0]].txout.script_pubkey == u.txout.script_pubkey)
+        } else {
+            None
+        };
+        match cluster {
+            Some(g) => groups[g].push(i),
+            None => groups.push(vec![i]),
+        }
+    }
+    groups
+}
+
+/// Raise `feerate` by a random amount of up to `percent`, so the PSBTs of a sweep can't be
+/// matched by their fee rate
+pub fn jitter_fee_rate(feerate: FeeRate, percent: u32) -> FeeRate {
+    if percent == 0 {
+        return feerate;
+    }
+    let factor = rand::thread_rng().gen_range(1.0, 1.0 + percent as f32 / 100.0);
+    FeeRate::from_sat_per_vb(feerate.as_sat_vb() * factor)
+}
+
+/// Script type as shown in errors
+pub fn script_type(script: &Script) -> &'static str {
+    if script.is_p2pkh() {
+        "p2pkh"
+    } else if script.is_p2sh() {
+        "p2sh"
+    } else if script.is_v0_p2wpkh() {
+        "p2wpkh"
+    } else if script.is_v0_p2wsh() {
+        "p2wsh"
+    } else {
+        "non-standard"
+    }
+}
+
+#[test]
+fn privacy_groups_test() {
+    use bdk::bitcoin::{Address, OutPoint, TxOut};
+    use bdk::KeychainKind;
+    use std::str::FromStr;
+
+    let utxo = |address: &str, vout: u32| LocalUtxo {
+        outpoint: OutPoint::from_str(&format!(
+            "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:{}",
+            vout
+        ))
+        .unwrap(),
+        txout: TxOut {
+            value: 10_000,
+            script_pubkey: Address::from_str(address).unwrap().script_pubkey(),
+        },
+        keychain: KeychainKind::External,
+    };
+    let unspent = vec![
+        utxo("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv", 0),
+        utxo("mqcxhkif3CQjmEWHGKJibMxRrNv8FKfnve", 1),
+        utxo("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv", 2),
+    ];
+
+    assert_eq!(
+        privacy_groups(&unspent, "utxo"),
+        vec![vec![0], vec![1], vec![2]]
+    );
+    assert_eq!(
+        privacy_groups(&unspent, "address"),
+        vec![vec![0, 2], vec![1]]
+    );
+
+    let feerate = FeeRate::from_sat_per_vb(10.0);
+    assert_eq!(jitter_fee_rate(feerate, 0), feerate);
+    for _ in 0..10 {
+        let jittered = jitter_fee_rate(feerate, 20).as_sat_vb();
+        assert!(jittered >= 10.0 && jittered < 12.0);
+    }
+
+    assert_eq!(script_type(&unspent[0].txout.script_pubkey), "p2pkh");
+}
@@ -0,0 +1,475 @@
+use bdk::bitcoin::consensus::deserialize;
+use bdk::bitcoin::{Address, Network, OutPoint, Script, Transaction, Txid};
+use bdk::blockchain::{Blockchain, Capability, Progress};
+use bdk::database::{BatchDatabase, BatchOperations, Database};
+use bdk::descriptor::checksum::get_checksum;
+use bdk::{Error, FeeRate, KeychainKind, LocalUtxo, TransactionDetails};
+use bitcoincore_rpc::jsonrpc;
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::errors::SweepError;
+
+/// Error code of Bitcoin Core for unknown transactions, addresses and keys
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// Transactions listed by `listtransactions` per call
+const PAGE_SIZE: usize = 1000;
+
+/// Bitcoin Core node queried over JSON-RPC, so no third party learns the addresses swept. The
+/// UTXOs are found with `scantxoutset`, which only sees the confirmed ones, or through a
+/// watch-only wallet of the node, which also sees the mempool.
+pub struct RpcBlockchain {
+    client: Client,
+    /// watch-only wallet the scripts are imported into
+    wallet: Option<String>,
+    network: Network,
+}
+
+/// Transaction of the wallet as found on the node, or on an Esplora server
+pub struct WalletTx {
+    pub tx: Transaction,
+    pub height: Option<u32>,
+    pub timestamp: u64,
+}
+
+impl RpcBlockchain {
+    pub fn new(
+        url: &str,
+        auth: Auth,
+        wallet: Option<String>,
+        network: Network,
+    ) -> Result<Self, Error> {
+        // wallet RPCs are sent to the endpoint of the wallet, the others work there as well
+        let url = match wallet {
+            Some(ref name) => format!("{}/wallet/{}", url.trim_end_matches('/'), name),
+            None => url.to_string(),
+        };
+        let client = Client::new(url, auth).map_err(|e| Error::Generic(e.to_string()))?;
+        Ok(RpcBlockchain {
+            client,
+            wallet,
+            network,
+        })
+    }
+
+    /// Fee rate of `estimatesmartfee`. A node without enough data yet, e.g. just started, has
+    /// no estimate, which is an error outside of regtest rather than the minimum relay fee
+    pub fn fee_rate(&self, target: usize) -> Result<FeeRate, SweepError> {
+        let estimate: Value = self.call("estimatesmartfee", &[json!(target)])?;
+        match estimate["feerate"].as_f64() {
+            // BTC/kvB
+            Some(rate) => Ok(FeeRate::from_sat_per_vb((rate * 100_000.0) as f32)),
+            None if self.network == Network::Regtest => Ok(FeeRate::default_min_relay_fee()),
+            None => Err(SweepError::Fee(format!(
+                "the node has no fee estimate for a target of {} blocks yet, pass --fee-rate",
+                target
+            ))),
+        }
+    }
+
+    fn call<T: DeserializeOwned>(&self, method: &str, args: &[Value]) -> Result<T, Error> {
+        self.client
+            .call(method, args)
+            .map_err(|e| Error::Generic(format!("{} failed: {}", method, e)))
+    }
+
+    /// Confirmed transactions with an output to `scripts` still unspent
+    fn scan(&self, scripts: &[Script]) -> Result<Vec<WalletTx>, Error> {
+        let descriptors: Vec<Value> = scripts
+            .iter()
+            .map(|script| json!(format!("raw({:x})", script)))
+            .collect();
+        let result: Value = self.call("scantxoutset", &[json!("start"), json!(descriptors)])?;
+
+        let mut heights = HashMap::new();
+        for unspent in result["unspents"].as_array().cloned().unwrap_or_default() {
+            heights.insert(
+                parse_txid(&unspent["txid"])?,
+                unspent["height"].as_u64().unwrap_or_default(),
+            );
+        }
+
+        let mut txs = Vec::new();
+        for (txid, height) in heights {
+            // without -txindex a transaction of a block is only found with its block hash
+            let hash = self
+                .client
+                .get_block_hash(height)
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            let tx = self
+                .client
+                .get_raw_transaction(&txid, Some(&hash))
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            let header = self
+                .client
+                .get_block_header(&hash)
+                .map_err(|e| Error::Generic(e.to_string()))?;
+            txs.push(WalletTx {
+                tx,
+                height: Some(height as u32),
+                timestamp: header.time as u64,
+            });
+        }
+        Ok(txs)
+    }
+
+    /// Transactions of the watch-only wallet `name` after importing `scripts`, confirmed or in
+    /// the mempool
+    fn wallet_history(&self, name: &str, scripts: &[Script]) -> Result<Vec<WalletTx>, Error> {
+        self.load_wallet(name)?;
+        self.import(scripts)?;
+
+        let mempool: HashSet<Txid> = self
+            .client
+            .get_raw_mempool()
+            .map_err(|e| Error::Generic(e.to_string()))?
+            .into_iter()
+            .collect();
+        let tip = self
+            .client
+            .get_block_count()
+            .map_err(|e| Error::Generic(e.to_string()))?;
+
+        let mut txids = Vec::new();
+        let mut skip = 0;
+        loop {
+            let page: Vec<Value> = self.call(
+                "listtransactions",
+                &[json!("*"), json!(PAGE_SIZE), json!(skip), json!(true)],
+            )?;
+            for entry in &page {
+                let txid = parse_txid(&entry["txid"])?;
+                if !txids.contains(&txid) {
+                    txids.push(txid);
+                }
+            }
+            if page.len() < PAGE_SIZE {
+                break;
+            }
+            skip += PAGE_SIZE;
+        }
+
+        let mut txs = Vec::new();
+        for txid in txids {
+            let info: Value =
+                self.call("gettransaction", &[json!(txid.to_string()), json!(true)])?;
+            let confirmations = info["confirmations"].as_i64().unwrap_or_default();
+            // conflicting with a confirmed transaction or replaced in the mempool
+            if confirmations < 0 || (confirmations == 0 && !mempool.contains(&txid)) {
+                continue;
+            }
+            txs.push(WalletTx {
+                tx: parse_tx(&info["hex"])?,
+                height: if confirmations > 0 {
+                    Some((tip + 1 - confirmations as u64) as u32)
+                } else {
+                    None
+                },
+                timestamp: info["blocktime"]
+                    .as_u64()
+                    .or_else(|| info["time"].as_u64())
+                    .unwrap_or_default(),
+            });
+        }
+        Ok(txs)
+    }
+
+    /// Load the wallet `name`, or create it as a blank watch-only descriptor wallet
+    fn load_wallet(&self, name: &str) -> Result<(), Error> {
+        let loaded: Vec<String> = self.call("listwallets", &[])?;
+        if loaded.iter().any(|wallet| wallet == name) {
+            return Ok(());
+        }
+        if self.call::<Value>("loadwallet", &[json!(name)]).is_err() {
+            // disable_private_keys, blank, passphrase, avoid_reuse, descriptors
+            self.call::<Value>(
+                "createwallet",
+                &[
+                    json!(name),
+                    json!(true),
+                    json!(true),
+                    json!(""),
+                    json!(false),
+                    json!(true),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Import the scripts the wallet doesn't watch yet. The node rescans the chain for them,
+    /// which takes long on mainnet, but only once.
+    fn import(&self, scripts: &[Script]) -> Result<(), Error> {
+        let mut requests = Vec::new();
+        for script in scripts {
+            let address = match Address::from_script(script, self.network) {
+                Some(address) => address,
+                None => continue,
+            };
+            let info: Value = self.call("getaddressinfo", &[json!(address.to_string())])?;
+            if info["ismine"].as_bool() == Some(true) || info["iswatchonly"].as_bool() == Some(true)
+            {
+                continue;
+            }
+            let descriptor = format!("raw({:x})", script);
+            let checksum = get_checksum(&descriptor)?;
+            requests.push(json!({
+                "desc": format!("{}#{}", descriptor, checksum),
+                "timestamp": 0,
+            }));
+        }
+        if requests.is_empty() {
+            return Ok(());
+        }
+
+        let results: Vec<Value> = self.call("importdescriptors", &[json!(requests)])?;
+        match results
+            .iter()
+            .find(|result| result["success"].as_bool() != Some(true))
+        {
+            Some(failed) => Err(Error::Generic(format!(
+                "importdescriptors failed: {}",
+                failed["error"]
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Blockchain for RpcBlockchain {
+    fn get_capabilities(&self) -> HashSet<Capability> {
+        let mut capabilities = HashSet::new();
+        if self.wallet.is_some() {
+            capabilities.insert(Capability::FullHistory);
+        }
+        capabilities
+    }
+
+    fn setup<D: BatchDatabase, P: 'static + Progress>(
+        &self,
+        _stop_gap: Option<usize>,
+        database: &mut D,
+        _progress_update: P,
+    ) -> Result<(), Error> {
+        // the wallet caches the scripts up to the address gap limit before syncing
+        let scripts = database.iter_script_pubkeys(None)?;
+        let txs = match self.wallet {
+            Some(ref name) => self.wallet_history(name, &scripts)?,
+            None => self.scan(&scripts)?,
+        };
+        store(database, &txs)
+    }
+
+    fn get_tx(&self, txid: &Txid) -> Result<Option<Transaction>, Error> {
+        // transactions in the mempool, and in blocks with -txindex
+        match self.client.get_raw_transaction(txid, None) {
+            Ok(tx) => return Ok(Some(tx)),
+            Err(ref e) if !not_found(e) => return Err(Error::Generic(e.to_string())),
+            Err(_) => {}
+        }
+        if self.wallet.is_none() {
+            return Ok(None);
+        }
+        match self
+            .client
+            .call::<Value>("gettransaction", &[json!(txid.to_string()), json!(true)])
+        {
+            Ok(info) => Ok(Some(parse_tx(&info["hex"])?)),
+            Err(ref e) if not_found(e) => Ok(None),
+            Err(e) => Err(Error::Generic(e.to_string())),
+        }
+    }
+
+    fn broadcast(&self, tx: &Transaction) -> Result<(), Error> {
+        self.client
+            .send_raw_transaction(tx)
+            .map(|_| ())
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    fn get_height(&self) -> Result<u32, Error> {
+        self.client
+            .get_block_count()
+            .map(|height| height as u32)
+            .map_err(|e| Error::Generic(e.to_string()))
+    }
+
+    fn estimate_fee(&self, target: usize) -> Result<FeeRate, Error> {
+        self.fee_rate(target)
+            .map_err(|e| Error::Generic(e.message().to_string()))
+    }
+}
+
+/// Store the transactions and the outputs of `txs` to the scripts of `database` which no
+/// transaction of `txs` spends
+pub fn store<D: BatchDatabase>(database: &mut D, txs: &[WalletTx]) -> Result<(), Error> {
+    let by_txid: HashMap<Txid, &Transaction> = txs.iter().map(|w| (w.tx.txid(), &w.tx)).collect();
+    let spent: HashSet<OutPoint> = txs
+        .iter()
+        .flat_map(|w| w.tx.input.iter().map(|input| input.previous_output))
+        .collect();
+
+    for utxo in database.iter_utxos()? {
+        database.del_utxo(&utxo.outpoint)?;
+    }
+
+    let mut last_index: HashMap<KeychainKind, u32> = HashMap::new();
+    for w in txs {
+        let txid = w.tx.txid();
+        let mut received = 0;
+        for (vout, output) in w.tx.output.iter().enumerate() {
+            let (keychain, index) =
+                match database.get_path_from_script_pubkey(&output.script_pubkey)? {
+                    Some(path) => path,
+                    None => continue,
+                };
+            received += output.value;
+            let last = last_index.entry(keychain).or_insert(index);
+            *last = (*last).max(index);
+
+            let outpoint = OutPoint::new(txid, vout as u32);
+            if !spent.contains(&outpoint) {
+                database.set_utxo(&LocalUtxo {
+                    outpoint,
+                    txout: output.clone(),
+                    keychain,
+                })?;
+            }
+        }
+
+        // the fees are known if all inputs are
+        let mut sent = 0;
+        let mut value_in = Some(0);
+        for input in &w.tx.input {
+            let previous = input.previous_output;
+            match by_txid
+                .get(&previous.txid)
+                .and_then(|tx| tx.output.get(previous.vout as usize))
+            {
+                Some(output) => {
+                    if database
+                        .get_path_from_script_pubkey(&output.script_pubkey)?
+                        .is_some()
+                    {
+                        sent += output.value;
+                    }
+                    value_in = value_in.map(|value| value + output.value);
+                }
+                None => value_in = None,
+            }
+        }
+        let value_out: u64 = w.tx.output.iter().map(|output| output.value).sum();
+
+        database.set_tx(&TransactionDetails {
+            transaction: Some(w.tx.clone()),
+            txid,
+            timestamp: w.timestamp,
+            received,
+            sent,
+            fees: value_in.map_or(0, |value| value.saturating_sub(value_out)),
+            height: w.height,
+        })?;
+    }
+
+    for (keychain, index) in last_index {
+        database.set_last_index(keychain, index)?;
+    }
+    Ok(())
+}
+
+fn parse_txid(txid: &Value) -> Result<Txid, Error> {
+    Txid::from_str(txid.as_str().unwrap_or_default())
+        .map_err(|e| Error::Generic(format!("invalid txid {}: {}", txid, e)))
+}
+
+fn parse_tx(hex: &Value) -> Result<Transaction, Error> {
+    let bytes = hex::decode(hex.as_str().unwrap_or_default())
+        .map_err(|e| Error::Generic(format!("invalid transaction: {}", e)))?;
+    Ok(deserialize(&bytes)?)
+}
+
+fn not_found(error: &bitcoincore_rpc::Error) -> bool {
+    match error {
+        bitcoincore_rpc::Error::JsonRpc(jsonrpc::error::Error::Rpc(e)) => {
+            e.code == RPC_INVALID_ADDRESS_OR_KEY
+        }
+        _ => false,
+    }
+}
+
+#[test]
+fn store_test() -> Result<(), Error> {
+    use bdk::bitcoin::{TxIn, TxOut};
+    use bdk::database::MemoryDatabase;
+
+    let mut database = MemoryDatabase::default();
+    let ours = Address::from_str("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")
+        .unwrap()
+        .script_pubkey();
+    let theirs = Address::from_str("mqcxhkif3CQjmEWHGKJibMxRrNv8FKfnve")
+        .unwrap()
+        .script_pubkey();
+    database.set_script_pubkey(&ours, KeychainKind::External, 3)?;
+
+    let funding = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn::default()],
+        output: vec![
+            TxOut {
+                value: 10_000,
+                script_pubkey: ours.clone(),
+            },
+            TxOut {
+                value: 20_000,
+                script_pubkey: ours.clone(),
+            },
+        ],
+    };
+    let spending = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(funding.txid(), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 9_000,
+            script_pubkey: theirs,
+        }],
+    };
+    let txs = vec![
+        WalletTx {
+            tx: funding.clone(),
+            height: Some(100),
+            timestamp: 1_600_000_000,
+        },
+        WalletTx {
+            tx: spending.clone(),
+            height: None,
+            timestamp: 1_600_000_600,
+        },
+    ];
+    store(&mut database, &txs)?;
+
+    // the first output is spent by the second transaction
+    let utxos = database.iter_utxos()?;
+    assert_eq!(utxos.len(), 1);
+    assert_eq!(utxos[0].outpoint, OutPoint::new(funding.txid(), 1));
+    assert_eq!(database.get_last_index(KeychainKind::External)?, Some(3));
+
+    let details = database.get_tx(&spending.txid(), false)?.unwrap();
+    assert_eq!(
+        (details.sent, details.fees, details.height),
+        (10_000, 1_000, None)
+    );
+    // the inputs of the funding transaction aren't known
+    let details = database.get_tx(&funding.txid(), false)?.unwrap();
+    assert_eq!((details.received, details.fees), (30_000, 0));
+
+    Ok(())
+}
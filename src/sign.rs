@@ -0,0 +1,296 @@
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey, Fingerprint};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction;
+use bdk::bitcoin::{Network, OutPoint, Script};
+use std::collections::BTreeSet;
+
+use crate::errors::SweepError;
+use crate::inspect::Descriptors;
+use crate::summary::{address, fee, signed_vsize};
+
+/// Build descriptors for an extended private key, so it can sign every input of `psbt`
+/// derived from it. A key on its own doesn't tell the script type nor the derivation path,
+/// so both are taken from the PSBT inputs (`bip32_derivation` and the spent output). Inputs
+/// of other scripts than single key ones are skipped with a warning.
+pub fn signing_descriptors(
+    xprv: &ExtendedPrivKey,
+    origin: &Option<(Fingerprint, DerivationPath)>,
+    psbt: &PartiallySignedTransaction,
+) -> Result<Vec<String>, SweepError> {
+    let secp = Secp256k1::new();
+
+    let (fingerprint, origin_path, key_origin) = match origin {
+        Some((fingerprint, path)) => (
+            *fingerprint,
+            path.as_ref().to_vec(),
+            format!(
+                "[{}{}]",
+                fingerprint,
+                path.as_ref()
+                    .iter()
+                    .map(|c| format!("/{}", c))
+                    .collect::<String>()
+            ),
+        ),
+        // a key without an origin is a master key, e.g. derived from a seed
+        None => (xprv.fingerprint(&secp), Vec::new(), "".to_string()),
+    };
+
+    let mut descriptors = BTreeSet::new();
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let utxo = input.witness_utxo.clone().or_else(|| {
+            let vout = psbt.global.unsigned_tx.input[i].previous_output.vout as usize;
+            input
+                .non_witness_utxo
+                .as_ref()
+                .and_then(|tx| tx.output.get(vout).cloned())
+        });
+        let utxo = match utxo {
+            Some(utxo) => utxo,
+            None => continue,
+        };
+
+        // the script type is the same for every key of the input
+        let script = &utxo.script_pubkey;
+        let wrap = if script.is_v0_p2wpkh() {
+            Some(("wpkh(", ")"))
+        } else if script.is_p2pkh() {
+            Some(("pkh(", ")"))
+        } else if script.is_p2sh()
+            && input
+                .redeem_script
+                .as_ref()
+                .map(|s| s.is_v0_p2wpkh())
+                .unwrap_or(false)
+        {
+            Some(("sh(wpkh(", "))"))
+        } else {
+            None
+        };
+
+        for (f, path) in input.bip32_derivation.values() {
+            let path = path.as_ref();
+            if *f != fingerprint
+                || path.len() <= origin_path.len()
+                || !path.starts_with(&origin_path)
+            {
+                continue;
+            }
+
+            let (open, close) = match wrap {
+                Some(wrap) => wrap,
+                None => {
+                    // the other inputs can still be signed
+                    eprintln!(
+                        "warning: input {} is not a single key script, it isn't signed. Pass a private descriptor to sign it",
+                        i
+                    );
+                    break;
+                }
+            };
+
+            // the last step is covered by the wildcard
+            let (last, steps) = path[origin_path.len()..].split_last().unwrap(); // safe
+            let wildcard = if last.is_hardened() { "*'" } else { "*" };
+            let key = format!(
+                "{}{}{}/{}",
+                key_origin,
+                xprv,
+                steps.iter().map(|c| format!("/{}", c)).collect::<String>(),
+                wildcard
+            );
+            descriptors.insert(format!("{}{}{}", open, key, close));
+        }
+    }
+
+    if descriptors.is_empty() {
+        return Err(SweepError::Signing(
+            "None of the PSBT single key inputs is derived from the private key".to_string(),
+        ));
+    }
+
+    Ok(descriptors.into_iter().collect())
+}
+
+/// Refuse a PSBT with an output not paying one of the expected addresses or descriptors, so a
+/// compromised coordinator can't divert the funds
+pub fn check_destinations(
+    psbt: &PartiallySignedTransaction,
+    addresses: &[Script],
+    descriptors: &Descriptors,
+    network: Network,
+) -> Result<(), SweepError> {
+    for (i, txout) in psbt.global.unsigned_tx.output.iter().enumerate() {
+        let script = &txout.script_pubkey;
+        if !addresses.contains(script) && descriptors.get(script).is_none() {
+            return Err(SweepError::Signing(format!(
+                "output {} pays {} sat to {}, which isn't an expected destination",
+                i,
+                txout.value,
+                address(script, network)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a PSBT paying more than `max_fee` sat or `max_fee_rate` sat/vB once signed, or one
+/// whose fee can't be told
+pub fn check_fee(
+    psbt: &PartiallySignedTransaction,
+    max_fee: Option<u64>,
+    max_fee_rate: Option<f32>,
+) -> Result<(), SweepError> {
+    if max_fee.is_none() && max_fee_rate.is_none() {
+        return Ok(());
+    }
+    let fee = fee(psbt).ok_or_else(|| {
+        SweepError::Signing(
+            "the fee is unknown as the PSBT lacks the UTXO of an input, it can't be checked"
+                .to_string(),
+        )
+    })?;
+    if let Some(max_fee) = max_fee {
+        if fee > max_fee {
+            return Err(SweepError::Signing(format!(
+                "the fee of {} sat is over --max-fee {}",
+                fee, max_fee
+            )));
+        }
+    }
+    if let Some(max_fee_rate) = max_fee_rate {
+        let vsize = signed_vsize(psbt).ok_or_else(|| {
+            SweepError::Signing(
+                "the size once signed is unknown, the fee rate can't be checked".to_string(),
+            )
+        })?;
+        let fee_rate = fee as f32 / vsize as f32;
+        if fee_rate > max_fee_rate {
+            return Err(SweepError::Signing(format!(
+                "the fee rate of {:.1} sat/vB is over --max-fee-rate {}",
+                fee_rate, max_fee_rate
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Outpoints of the inputs without a witness_utxo nor a non_witness_utxo, whose value can't be
+/// checked nor signed for a segwit input
+pub fn missing_utxos(psbt: &PartiallySignedTransaction) -> Vec<OutPoint> {
+    psbt.global
+        .unsigned_tx
+        .input
+        .iter()
+        .zip(psbt.inputs.iter())
+        .filter(|(_, input)| input.witness_utxo.is_none() && input.non_witness_utxo.is_none())
+        .map(|(txin, _)| txin.previous_output)
+        .collect()
+}
+
+#[test]
+fn check_test() {
+    use bdk::bitcoin::{Address, Transaction, TxIn, TxOut};
+    use std::str::FromStr;
+
+    let own = Address::from_str("bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs").unwrap();
+    let other = Address::from_str("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26").unwrap();
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::from_str(
+                "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:0",
+            )
+            .unwrap(),
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: 99_000,
+            script_pubkey: other.script_pubkey(),
+        }],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    let none = Descriptors::new(&[], Network::Regtest, 0).unwrap();
+
+    assert_eq!(missing_utxos(&psbt).len(), 1);
+    assert!(check_fee(&psbt, None, None).is_ok());
+    assert!(check_fee(&psbt, Some(100_000), None).is_err());
+
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: 100_000,
+        script_pubkey: own.script_pubkey(),
+    });
+    assert!(missing_utxos(&psbt).is_empty());
+    // the P2SH output is a byte larger than a P2WPKH one: 1000 sat for 111 vB, 9.009 sat/vB
+    assert_eq!(signed_vsize(&psbt), Some(111));
+    assert!(check_fee(&psbt, Some(1_000), None).is_ok());
+    assert!(check_fee(&psbt, Some(999), None).is_err());
+    assert!(check_fee(&psbt, None, Some(9.01)).is_ok());
+    assert!(check_fee(&psbt, None, Some(9.0)).is_err());
+
+    assert!(check_destinations(&psbt, &[other.script_pubkey()], &none, Network::Regtest).is_ok());
+    assert!(check_destinations(&psbt, &[own.script_pubkey()], &none, Network::Regtest).is_err());
+}
+
+#[test]
+fn signing_descriptors_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::util::bip32::ChildNumber;
+    use bdk::bitcoin::{PublicKey, Transaction, TxIn};
+    use std::str::FromStr;
+
+    let xprv = ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPe5YMU9gHen4Ez3ApihUfykaqUorj9t6FDqy3nP6eoXiAo2ssvpAjoLroQxHqr3R5nE3a5dU3DHTjTgJDd7zrbniJr6nrCzd").unwrap();
+    let fingerprint = xprv.fingerprint(&Secp256k1::new());
+    let path = DerivationPath::from(vec![
+        ChildNumber::from_hardened_idx(84).unwrap(),
+        ChildNumber::from_hardened_idx(1).unwrap(),
+        ChildNumber::from_hardened_idx(0).unwrap(),
+        ChildNumber::from_normal_idx(0).unwrap(),
+        ChildNumber::from_normal_idx(0).unwrap(),
+    ]);
+    // only the fingerprint and the path of the derivation matter
+    let pubkey =
+        PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn::default(), TxIn::default()],
+        output: vec![],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    // a p2wpkh input and a p2wsh one
+    let scripts = [
+        format!("0014{}", "00".repeat(20)),
+        format!("0020{}", "00".repeat(32)),
+    ];
+    for (input, script) in psbt.inputs.iter_mut().zip(&scripts) {
+        input.witness_utxo = Some(bdk::bitcoin::TxOut {
+            value: 10_000,
+            script_pubkey: Script::from(hex::decode(script).unwrap()),
+        });
+        input
+            .bip32_derivation
+            .insert(pubkey, (fingerprint, path.clone()));
+    }
+
+    // a multisig input has several keys, it is skipped with a single warning
+    let other =
+        PublicKey::from_str("02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5")
+            .unwrap();
+    psbt.inputs[1]
+        .bip32_derivation
+        .insert(other, (fingerprint, path.clone()));
+
+    // the p2wsh input is skipped
+    assert_eq!(
+        signing_descriptors(&xprv, &None, &psbt)?,
+        vec![format!("wpkh({}/84'/1'/0'/0/*)", xprv)]
+    );
+
+    psbt.inputs.remove(0);
+    assert!(signing_descriptors(&xprv, &None, &psbt).is_err());
+
+    Ok(())
+}
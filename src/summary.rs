@@ -0,0 +1,301 @@
+use bdk::bitcoin::consensus::serialize;
+use bdk::bitcoin::util::psbt::{Input, PartiallySignedTransaction};
+use bdk::bitcoin::{Address, Network, Script, TxOut};
+use std::fmt::Write;
+use std::io::BufRead;
+
+use crate::errors::SweepError;
+
+/// Push of a DER signature with its sighash flag, at most
+const SIGNATURE: usize = 1 + 73;
+/// Push of a compressed public key
+const PUBKEY: usize = 1 + 33;
+
+/// Output spent by an input of a PSBT, if the PSBT has it
+pub fn spent_output(psbt: &PartiallySignedTransaction, index: usize) -> Option<TxOut> {
+    let input = psbt.inputs.get(index)?;
+    let outpoint = psbt.global.unsigned_tx.input.get(index)?.previous_output;
+    input.witness_utxo.clone().or_else(|| {
+        input
+            .non_witness_utxo
+            .as_ref()
+            .filter(|tx| tx.txid() == outpoint.txid)
+            .and_then(|tx| tx.output.get(outpoint.vout as usize).cloned())
+    })
+}
+
+/// Value of the inputs of a PSBT, unless one is missing
+pub fn value_in(psbt: &PartiallySignedTransaction) -> Option<u64> {
+    (0..psbt.inputs.len())
+        .map(|index| spent_output(psbt, index).map(|txout| txout.value))
+        .sum()
+}
+
+/// Fee of a PSBT, unless the value of an input is missing
+pub fn fee(psbt: &PartiallySignedTransaction) -> Option<u64> {
+    let value_out: u64 = psbt
+        .global
+        .unsigned_tx
+        .output
+        .iter()
+        .map(|txout| txout.value)
+        .sum();
+    value_in(psbt)?.checked_sub(value_out)
+}
+
+/// Virtual size of the transaction of a PSBT once signed: exact for the finalized inputs, and
+/// the largest for the script type of the others. None for a script type it can't tell, e.g.
+/// a P2WSH which isn't a multisig
+pub fn signed_vsize(psbt: &PartiallySignedTransaction) -> Option<usize> {
+    // the unsigned transaction has empty scriptSigs and no witnesses
+    let mut weight = serialize(&psbt.global.unsigned_tx).len() * 4;
+    let mut witnesses = Vec::new();
+    for (index, input) in psbt.inputs.iter().enumerate() {
+        let (script_sig, witness) = match (&input.final_script_sig, &input.final_script_witness) {
+            (None, None) => satisfaction(input, &spent_output(psbt, index)?.script_pubkey)?,
+            (script_sig, witness) => (
+                script_sig.as_ref().map_or(0, |script| script.len()),
+                witness.as_ref().map(|witness| {
+                    varint_len(witness.len())
+                        + witness
+                            .iter()
+                            .map(|item| varint_len(item.len()) + item.len())
+                            .sum::<usize>()
+                }),
+            ),
+        };
+        // the empty scriptSig has its length already
+        weight += (script_sig + varint_len(script_sig) - 1) * 4;
+        witnesses.push(witness);
+    }
+    if witnesses.iter().any(Option::is_some) {
+        // marker and flag, and an empty witness for the inputs without one
+        weight += 2 + witnesses
+            .iter()
+            .map(|witness| witness.unwrap_or(1))
+            .sum::<usize>();
+    }
+    Some((weight + 3) / 4)
+}
+
+/// Size of the scriptSig and of the witness, if any, satisfying an input
+fn satisfaction(input: &Input, script_pubkey: &Script) -> Option<(usize, Option<usize>)> {
+    let p2wpkh = 1 + SIGNATURE + PUBKEY;
+    if script_pubkey.is_p2pkh() {
+        Some((SIGNATURE + PUBKEY, None))
+    } else if script_pubkey.is_v0_p2wpkh() {
+        Some((0, Some(p2wpkh)))
+    } else if script_pubkey.is_v0_p2wsh() {
+        Some((0, Some(multisig_witness(input.witness_script.as_ref()?)?)))
+    } else if script_pubkey.is_p2sh() {
+        let redeem_script = input.redeem_script.as_ref()?;
+        let push = push_len(redeem_script.len());
+        if redeem_script.is_v0_p2wpkh() {
+            Some((push, Some(p2wpkh)))
+        } else if redeem_script.is_v0_p2wsh() {
+            let witness = multisig_witness(input.witness_script.as_ref()?)?;
+            Some((push, Some(witness)))
+        } else {
+            // OP_0 for the extra item of OP_CHECKMULTISIG
+            Some((1 + multisig(redeem_script)? * SIGNATURE + push, None))
+        }
+    } else {
+        None
+    }
+}
+
+/// Number of signatures required to spend an input, for the script types signed_vsize knows
+pub fn required_signatures(input: &Input, script_pubkey: &Script) -> Option<usize> {
+    let script = match &input.redeem_script {
+        Some(redeem_script) if script_pubkey.is_p2sh() => redeem_script,
+        _ => script_pubkey,
+    };
+    if script.is_p2pkh() || script.is_v0_p2wpkh() {
+        Some(1)
+    } else if script.is_v0_p2wsh() {
+        multisig(input.witness_script.as_ref()?)
+    } else if script_pubkey.is_p2sh() {
+        multisig(script)
+    } else {
+        None
+    }
+}
+
+/// Number of signatures required by a multisig script
+fn multisig(script: &Script) -> Option<usize> {
+    let bytes = script.as_bytes();
+    match (bytes.first(), bytes.last()) {
+        // OP_PUSHNUM_1 to OP_PUSHNUM_16, OP_CHECKMULTISIG
+        (Some(&m), Some(&0xae)) if (0x51..=0x60).contains(&m) => Some((m - 0x50) as usize),
+        _ => None,
+    }
+}
+
+fn multisig_witness(witness_script: &Script) -> Option<usize> {
+    let m = multisig(witness_script)?;
+    let len = witness_script.len();
+    Some(varint_len(m + 2) + 1 + m * SIGNATURE + varint_len(len) + len)
+}
+
+fn push_len(len: usize) -> usize {
+    match len {
+        0..=75 => 1 + len,
+        76..=255 => 2 + len,
+        _ => 3 + len,
+    }
+}
+
+fn varint_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+/// Address of a script, or the script itself in hex
+pub fn address(script: &Script, network: Network) -> String {
+    Address::from_script(script, network)
+        .map(|address| address.to_string())
+        .unwrap_or_else(|| format!("script {:x}", script))
+}
+
+fn btc(sat: u64) -> String {
+    format!("{}.{:08} BTC", sat / 100_000_000, sat % 100_000_000)
+}
+
+/// First and last characters of an address in bold and underlined, as those are the ones
+/// checked against the expected address
+fn highlight(address: &str, color: bool) -> String {
+    let ends = 6;
+    if !color || address.len() <= 2 * ends || !address.is_ascii() {
+        return address.to_string();
+    }
+    let (start, rest) = address.split_at(ends);
+    let (middle, end) = rest.split_at(rest.len() - ends);
+    format!("\x1b[1;4m{}\x1b[0m{}\x1b[1;4m{}\x1b[0m", start, middle, end)
+}
+
+/// Table of the inputs, the outputs and the fee of a PSBT, for the user to check
+pub fn summary(psbt: &PartiallySignedTransaction, network: Network, color: bool) -> String {
+    let tx = &psbt.global.unsigned_tx;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "Inputs");
+    for (index, txin) in tx.input.iter().enumerate() {
+        let _ = match spent_output(psbt, index) {
+            Some(txout) => writeln!(
+                out,
+                "  {:>20}  {}  {}",
+                btc(txout.value),
+                txin.previous_output,
+                address(&txout.script_pubkey, network)
+            ),
+            None => writeln!(out, "  {:>20}  {}", "unknown value", txin.previous_output),
+        };
+    }
+    let _ = writeln!(out, "Outputs");
+    for txout in &tx.output {
+        let _ = writeln!(
+            out,
+            "  {:>20}  {}",
+            btc(txout.value),
+            highlight(&address(&txout.script_pubkey, network), color)
+        );
+    }
+
+    match (fee(psbt), value_in(psbt)) {
+        (Some(fee), Some(value_in)) => {
+            let _ = writeln!(
+                out,
+                "Fee       {} sat, {:.2}% of the funds",
+                fee,
+                fee as f64 * 100.0 / value_in.max(1) as f64
+            );
+            let _ = match signed_vsize(psbt) {
+                Some(vsize) => writeln!(
+                    out,
+                    "Fee rate  {:.1} sat/vB, for {} vB once signed",
+                    fee as f64 / vsize as f64,
+                    vsize
+                ),
+                None => writeln!(out, "Fee rate  unknown, the size once signed is unknown"),
+            };
+        }
+        _ => {
+            let _ = writeln!(out, "Fee       unknown, the value of an input is missing");
+        }
+    }
+    out
+}
+
+/// Show the PSBT(s) on stderr and wait for the user to type yes on stdin
+pub fn review(
+    psbts: &[PartiallySignedTransaction],
+    network: Network,
+    action: &str,
+) -> Result<(), SweepError> {
+    let color = atty::is(atty::Stream::Stderr);
+    for (index, psbt) in psbts.iter().enumerate() {
+        if psbts.len() > 1 {
+            eprintln!("PSBT {} of {}", index + 1, psbts.len());
+        }
+        eprintln!("{}", summary(psbt, network, color));
+    }
+    eprint!("{}? Type yes to confirm: ", action);
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| SweepError::Internal(format!("unable to read the confirmation: {}", e)))?;
+    if answer.trim().eq_ignore_ascii_case("yes") {
+        Ok(())
+    } else {
+        Err(SweepError::Aborted("not confirmed by the user".to_string()))
+    }
+}
+
+#[test]
+fn summary_test() {
+    use bdk::bitcoin::{OutPoint, Transaction, TxIn};
+    use std::str::FromStr;
+
+    let address = Address::from_str("bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs").unwrap();
+    let tx = Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::from_str(
+                "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:0",
+            )
+            .unwrap(),
+            ..TxIn::default()
+        }],
+        output: vec![TxOut {
+            value: 99_000,
+            script_pubkey: address.script_pubkey(),
+        }],
+    };
+    let mut psbt = PartiallySignedTransaction::from_unsigned_tx(tx).unwrap();
+    assert_eq!(fee(&psbt), None);
+    assert_eq!(signed_vsize(&psbt), None);
+
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: 100_000,
+        script_pubkey: address.script_pubkey(),
+    });
+    assert_eq!(fee(&psbt), Some(1_000));
+    // one P2WPKH input and output, with the largest signature
+    assert_eq!(signed_vsize(&psbt), Some(110));
+
+    let summary = summary(&psbt, Network::Regtest, false);
+    assert!(summary.contains("0.00099000 BTC  bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs"));
+    assert!(summary.contains("Fee       1000 sat, 1.00% of the funds"));
+    assert!(summary.contains("Fee rate  9.1 sat/vB, for 110 vB once signed"));
+
+    assert_eq!(
+        highlight("bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs", true),
+        "\x1b[1;4mbcrt1q\x1b[0mzg4mckdh50nwdm9hkzq06528rsu73hjx\x1b[1;4mytqkxs\x1b[0m"
+    );
+}
@@ -0,0 +1,183 @@
+use bdk::database::BatchDatabase;
+use bdk::{KeychainKind, Wallet};
+
+use crate::errors::SweepError;
+use crate::plan::PolicyPath;
+
+/// nLockTime values from here on are unix timestamps, below are block heights
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Absolute timelock (`after()`) the descriptors of `wallet` require to be spent along `path`,
+/// if any. Relative timelocks (`older()`) are applied by bdk to the sequence of the inputs.
+pub fn required_locktime<B, D: BatchDatabase>(
+    wallet: &Wallet<B, D>,
+    path: &PolicyPath,
+) -> Result<Option<u32>, SweepError> {
+    let mut required = None;
+    for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+        if let Some(policy) = wallet.policies(*keychain)? {
+            let condition = policy.get_condition(path)?;
+            required = required.max(condition.timelock);
+        }
+    }
+    Ok(required)
+}
+
+/// nLockTime of a sweep: the one requested, or else the current block height `tip` against fee
+/// sniping. The timelock `required` by the descriptors takes precedence over the tip and is a
+/// lower bound of the requested one.
+pub fn sweep_locktime(
+    requested: Option<u32>,
+    required: Option<u32>,
+    tip: u32,
+) -> Result<u32, SweepError> {
+    match (requested, required) {
+        (Some(requested), Some(required))
+            if (requested >= LOCKTIME_THRESHOLD) != (required >= LOCKTIME_THRESHOLD) =>
+        {
+            Err(SweepError::Cli(format!(
+                "locktime {} and the timelock {} required by the descriptor are not both block heights or both timestamps",
+                requested, required
+            )))
+        }
+        (Some(requested), Some(required)) if requested < required => Err(SweepError::Cli(format!(
+            "locktime {} is before the timelock {} required by the descriptor",
+            requested, required
+        ))),
+        (Some(requested), _) => Ok(requested),
+        (None, Some(required)) if required >= LOCKTIME_THRESHOLD => Ok(required),
+        (None, Some(required)) => Ok(required.max(tip)),
+        (None, None) => Ok(tip),
+    }
+}
+
+#[test]
+fn locktime_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::Network;
+    use bdk::database::MemoryDatabase;
+
+    let wallet = Wallet::new_offline(
+        "wsh(and_v(v:pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),after(800000)))",
+        Some("wsh(and_v(v:pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/1/*),after(800000)))"),
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    assert_eq!(
+        required_locktime(&wallet, &PolicyPath::new())?,
+        Some(800_000)
+    );
+
+    // a relative timelock doesn't require an nLockTime
+    let wallet = Wallet::new_offline(
+        "wsh(and_v(v:pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),older(144)))",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    assert_eq!(required_locktime(&wallet, &PolicyPath::new())?, None);
+
+    // a threshold with several spending paths needs --policy-path
+    let wallet = Wallet::new_offline(
+        "wsh(thresh(2,pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),s:pk(tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/0/*),sln:after(800000)))",
+        None,
+        Network::Testnet,
+        MemoryDatabase::default(),
+    )?;
+    match required_locktime(&wallet, &PolicyPath::new()) {
+        Err(SweepError::Cli(message)) => assert!(message.contains("--policy-path")),
+        other => panic!("expected a --policy-path error, got {:?}", other),
+    }
+
+    // anti fee sniping
+    assert_eq!(sweep_locktime(None, None, 700_000)?, 700_000);
+    assert_eq!(sweep_locktime(None, Some(800_000), 700_000)?, 800_000);
+    assert_eq!(sweep_locktime(None, Some(600_000), 700_000)?, 700_000);
+    assert_eq!(
+        sweep_locktime(None, Some(1_700_000_000), 700_000)?,
+        1_700_000_000
+    );
+
+    assert_eq!(sweep_locktime(Some(0), None, 700_000)?, 0);
+    assert_eq!(
+        sweep_locktime(Some(900_000), Some(800_000), 700_000)?,
+        900_000
+    );
+    assert!(sweep_locktime(Some(750_000), Some(800_000), 700_000).is_err());
+    assert!(sweep_locktime(Some(1_700_000_000), Some(800_000), 700_000).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn relative_timelock_sequence_test() -> Result<(), SweepError> {
+    use bdk::bitcoin::{Address, Network, OutPoint, Transaction, TxIn, TxOut};
+    use bdk::blockchain::noop_progress;
+    use bdk::database::MemoryDatabase;
+    use bdk::wallet::AddressIndex;
+    use std::str::FromStr;
+
+    use crate::bump::signals_replaceability;
+    use crate::offline::{OfflineBlockchain, UtxoSet};
+
+    let descriptor = "wsh(and_v(v:pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),older(144)))";
+    let descriptor_chg = "wsh(and_v(v:pk(tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/1/*),older(144)))";
+    let receive = Wallet::new_offline(
+        descriptor,
+        None,
+        Network::Regtest,
+        MemoryDatabase::default(),
+    )?;
+    let change = Wallet::new_offline(
+        descriptor_chg,
+        None,
+        Network::Regtest,
+        MemoryDatabase::default(),
+    )?;
+    let mut set = UtxoSet::new(Network::Regtest, 150);
+    for address in &[
+        receive.get_address(AddressIndex::Peek(0))?,
+        change.get_address(AddressIndex::Peek(3))?,
+    ] {
+        let tx = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 50_000,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        set.push(OutPoint::new(tx.txid(), 0), &tx);
+    }
+
+    // the sweep is built like in main.rs
+    let wallet = Wallet::new(
+        descriptor,
+        Some(descriptor_chg),
+        Network::Regtest,
+        MemoryDatabase::default(),
+        OfflineBlockchain::new(&set, Network::Regtest)?,
+    )?;
+    wallet.sync(noop_progress(), None)?;
+    let locktime = sweep_locktime(None, required_locktime(&wallet, &PolicyPath::new())?, 150)?;
+    let mut builder = wallet.build_tx();
+    builder.drain_wallet();
+    builder
+        .set_single_recipient(
+            Address::from_str("bcrt1q6rz28mcfaxtmd6v789l9rrlrusdprr9pz3cppk")
+                .unwrap()
+                .script_pubkey(),
+        )
+        .enable_rbf()
+        .nlocktime(locktime);
+    let (psbt, _) = builder.finish()?;
+
+    // bdk sets the relative timelock in the sequence of every input, which signals
+    // replaceability as well
+    let tx = psbt.global.unsigned_tx;
+    assert_eq!(tx.input.len(), 2);
+    assert!(tx.input.iter().all(|input| input.sequence == 144));
+    assert!(signals_replaceability(&tx));
+
+    Ok(())
+}
@@ -1,24 +1,314 @@
 use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::util::address::Payload;
+use bdk::bitcoin::util::bip32::{
+    ChainCode, DerivationPath, ExtendedPrivKey, ExtendedPubKey, Fingerprint,
+};
+use bdk::bitcoin::{Address, Network, PrivateKey, PublicKey};
 use serde::{Deserialize, Serialize};
 use serde_cbor::tags::Tagged;
 use serde_cbor::value::Value;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 use std::convert::TryInto;
+use std::str::FromStr;
 use ur_rs::bytewords;
 
 use crate::errors::SweepError;
 
 pub fn psbt_as_ur(psbt: Vec<u8>) -> Result<String, SweepError> {
-    use serde_cbor::to_vec;
-    let arr = Value::Bytes(psbt);
-    let psbt_ = to_vec(&arr)?;
-    let bytewrds = bytewords::encode(&psbt_, &bytewords::Style::Minimal);
-    let psbt_ur = "ur:crypto-psbt/".to_owned() + &bytewrds;
-    Ok(psbt_ur)
+    cbor_as_ur("crypto-psbt", &Value::Bytes(psbt))
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+pub fn is_ur_psbt(ur: &str) -> bool {
+    ur.to_lowercase().starts_with("ur:crypto-psbt/")
+}
+
+/// Serialized PSBT of a crypto-psbt
+pub fn parse_ur_psbt(ur: &str) -> Result<Vec<u8>, SweepError> {
+    let cbor = split_ur_as(ur, "crypto-psbt")?;
+    match cbor_value("crypto-psbt", &cbor)? {
+        Value::Bytes(psbt) => Ok(psbt),
+        _ => Err(ur_error("crypto-psbt: expected a byte string".to_string())),
+    }
+}
+
+fn cbor_as_ur(ur_type: &str, data: &Value) -> Result<String, SweepError> {
+    let cbor = serde_cbor::to_vec(data)?;
+    let bytewrds = bytewords::encode(&cbor, &bytewords::Style::Minimal);
+    Ok(format!("ur:{}/{}", ur_type, bytewrds))
+}
+
+/// Encode a descriptor, an extended key (with an optional origin) or an address
+/// given in Bitcoin Core compatible format to the matching UR type
+pub fn encode_as_ur(input: &str) -> Result<String, SweepError> {
+    if input.contains('(') {
+        descriptor_as_ur(input)
+    } else if Address::from_str(input).is_ok() {
+        address_as_ur(input)
+    } else {
+        hdkey_as_ur(input)
+    }
+}
+
+/// Encode a Bitcoin Core output descriptor as `ur:crypto-output`
+pub fn descriptor_as_ur(desc: &str) -> Result<String, SweepError> {
+    // the checksum is not part of the UR encoding
+    let desc = desc.split('#').next().unwrap_or(desc); // safe
+    let data = descriptor_to_cbor(desc.trim())?;
+    cbor_as_ur("crypto-output", &data)
+}
+
+/// Encode an xpub or an xprv, e.g. `[c258d2e4/84h/1h/0h]tpub...`, as `ur:crypto-hdkey`
+pub fn hdkey_as_ur(key: &str) -> Result<String, SweepError> {
+    match key_to_cbor(key)? {
+        // the tag is implied by the UR type
+        Value::Tag(303, hdkey) => cbor_as_ur("crypto-hdkey", &hdkey),
+        _ => Err(SweepError::Descriptor(format!(
+            "not an extended key: {}",
+            key
+        ))),
+    }
+}
+
+/// Encode a Bitcoin address as `ur:crypto-address`
+pub fn address_as_ur(address: &str) -> Result<String, SweepError> {
+    let address = Address::from_str(address)?;
+
+    // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-009-address.md
+    let (type_, data) = match address.payload {
+        Payload::PubkeyHash(h) => (Some(0), h[..].to_vec()),
+        Payload::ScriptHash(h) => (Some(1), h[..].to_vec()),
+        Payload::WitnessProgram { version, program } if version.to_u8() == 0 => {
+            if program.len() == 20 {
+                (Some(2), program)
+            } else {
+                // p2wsh has no type of its own, it is recognized by the length of the data
+                (None, program)
+            }
+        }
+        _ => {
+            return Err(SweepError::Address(format!(
+                "unsupported address type: {}",
+                address
+            )))
+        }
+    };
+
+    let mut map = BTreeMap::new();
+    if let Some(info) = coin_info_to_cbor(address.network) {
+        map.insert(Value::Integer(1), info);
+    }
+    if let Some(t) = type_ {
+        map.insert(Value::Integer(2), Value::Integer(t));
+    }
+    map.insert(Value::Integer(3), Value::Bytes(data));
+
+    cbor_as_ur("crypto-address", &Value::Map(map))
+}
+
+fn descriptor_to_cbor(desc: &str) -> Result<Value, SweepError> {
+    // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-010-output-desc.md
+    let (func, args) = desc
+        .split_once('(')
+        .and_then(|(func, rest)| rest.strip_suffix(')').map(|args| (func, args)))
+        .ok_or_else(|| SweepError::Descriptor(format!("malformed script expression: {}", desc)))?;
+
+    let value = match func {
+        "sh" => Value::Tag(400, Box::new(descriptor_to_cbor(args)?)),
+        "wsh" => Value::Tag(401, Box::new(descriptor_to_cbor(args)?)),
+        "pk" => Value::Tag(402, Box::new(key_to_cbor(args)?)),
+        "pkh" => Value::Tag(403, Box::new(key_to_cbor(args)?)),
+        "wpkh" => Value::Tag(404, Box::new(key_to_cbor(args)?)),
+        "multi" | "sortedmulti" => {
+            let mut args = args.split(',');
+            let threshold: u32 = args
+                .next()
+                .and_then(|t| t.trim().parse().ok())
+                .ok_or_else(|| SweepError::Descriptor("multi missing threshold".to_string()))?;
+            let keys = args
+                .map(|k| key_to_cbor(k.trim()))
+                .collect::<Result<Vec<Value>, SweepError>>()?;
+            if keys.is_empty() {
+                return Err(SweepError::Descriptor("multi missing keys".to_string()));
+            }
+
+            let mut map = BTreeMap::new();
+            map.insert(Value::Integer(1), Value::Integer(threshold.into()));
+            map.insert(Value::Integer(2), Value::Array(keys));
+
+            let tag = if func == "multi" { 406 } else { 407 };
+            Value::Tag(tag, Box::new(Value::Map(map)))
+        }
+        _ => {
+            return Err(SweepError::Descriptor(format!(
+                "unsupported script expression: {}. crypto-output only supports sh, wsh, pk, pkh, wpkh, multi and sortedmulti, pass miniscript descriptors in Bitcoin Core format",
+                func
+            )))
+        }
+    };
+
+    Ok(value)
+}
+
+fn key_to_cbor(key: &str) -> Result<Value, SweepError> {
+    let (origin, key) = if let Some(key) = key.strip_prefix('[') {
+        let (origin, key) = key
+            .split_once(']')
+            .ok_or_else(|| SweepError::Descriptor("key origin: missing ]".to_string()))?;
+        (Some(origin), key)
+    } else {
+        (None, key)
+    };
+
+    let (key, children) = match key.split_once('/') {
+        Some((key, children)) => (key, Some(children)),
+        None => (key, None),
+    };
+
+    if let Ok(xpub) = ExtendedPubKey::from_str(key) {
+        let hdkey = hdkey_to_cbor(
+            xpub.public_key.to_bytes(),
+            xpub.chain_code,
+            xpub.network,
+            xpub.depth,
+            xpub.parent_fingerprint,
+            origin,
+            children,
+        )?;
+        Ok(Value::Tag(303, Box::new(Value::Map(hdkey))))
+    } else if let Ok(xprv) = ExtendedPrivKey::from_str(key) {
+        // private key data is prefixed with 0x00 to make it 33 bytes long
+        let mut key_data = vec![0u8];
+        key_data.extend(xprv.private_key.to_bytes());
+
+        let mut hdkey = hdkey_to_cbor(
+            key_data,
+            xprv.chain_code,
+            xprv.network,
+            xprv.depth,
+            xprv.parent_fingerprint,
+            origin,
+            children,
+        )?;
+        hdkey.insert(Value::Integer(2), Value::Bool(true));
+        Ok(Value::Tag(303, Box::new(Value::Map(hdkey))))
+    } else if origin.is_some() || children.is_some() {
+        Err(SweepError::Descriptor(format!(
+            "derivation is only supported for extended keys: {}",
+            key
+        )))
+    } else {
+        // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-008-eckey.md
+        let mut map = BTreeMap::new();
+        if let Ok(pubkey) = PublicKey::from_str(key) {
+            map.insert(Value::Integer(3), Value::Bytes(pubkey.to_bytes()));
+        } else if let Ok(privkey) = PrivateKey::from_wif(key) {
+            map.insert(Value::Integer(2), Value::Bool(true));
+            map.insert(Value::Integer(3), Value::Bytes(privkey.to_bytes()));
+        } else {
+            return Err(SweepError::Descriptor(format!("unrecognized key: {}", key)));
+        }
+        Ok(Value::Tag(306, Box::new(Value::Map(map))))
+    }
+}
+
+fn hdkey_to_cbor(
+    key_data: Vec<u8>,
+    chain_code: ChainCode,
+    network: Network,
+    depth: u8,
+    parent_fingerprint: Fingerprint,
+    origin: Option<&str>,
+    children: Option<&str>,
+) -> Result<BTreeMap<Value, Value>, SweepError> {
+    // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-007-hdkey.md
+    let mut map = BTreeMap::new();
+    map.insert(Value::Integer(3), Value::Bytes(key_data));
+    map.insert(
+        Value::Integer(4),
+        Value::Bytes(chain_code.as_bytes().to_vec()),
+    );
+    if let Some(info) = coin_info_to_cbor(network) {
+        map.insert(Value::Integer(5), info);
+    }
+
+    if let Some(origin) = origin {
+        let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+        let fingerprint = hex::decode(fingerprint)
+            .ok()
+            .and_then(|f| f.try_into().ok())
+            .map(u32::from_be_bytes)
+            .ok_or_else(|| {
+                SweepError::Descriptor(format!("key origin: invalid fingerprint: {}", fingerprint))
+            })?;
+        let components = path_to_cbor(path)?;
+
+        let mut keypath = BTreeMap::new();
+        keypath.insert(Value::Integer(2), Value::Integer(fingerprint.into()));
+        // depth takes precedence over the number of components when the key is decoded,
+        // so it is only written when it does not contradict the origin
+        if depth as usize >= components.len() / 2 {
+            keypath.insert(Value::Integer(3), Value::Integer(depth.into()));
+        }
+        keypath.insert(Value::Integer(1), Value::Array(components));
+        map.insert(
+            Value::Integer(6),
+            Value::Tag(304, Box::new(Value::Map(keypath))),
+        );
+    }
+
+    if let Some(children) = children {
+        let mut keypath = BTreeMap::new();
+        keypath.insert(Value::Integer(1), Value::Array(path_to_cbor(children)?));
+        map.insert(
+            Value::Integer(7),
+            Value::Tag(304, Box::new(Value::Map(keypath))),
+        );
+    }
+
+    let parent_fingerprint = u32::from_be_bytes(*parent_fingerprint.as_bytes());
+    if parent_fingerprint != 0 {
+        map.insert(Value::Integer(8), Value::Integer(parent_fingerprint.into()));
+    }
+
+    Ok(map)
+}
+
+fn path_to_cbor(path: &str) -> Result<Vec<Value>, SweepError> {
+    let mut components = Vec::new();
+    for step in path.split('/').filter(|s| !s.is_empty()) {
+        let hardened = step.ends_with('h') || step.ends_with('\'');
+        let index = step.trim_end_matches(|c| c == 'h' || c == '\'');
+        if index == "*" {
+            components.push(Value::Array(Vec::new()));
+        } else {
+            let index = index
+                .parse::<u32>()
+                .ok()
+                .filter(|i| *i < 0x8000_0000)
+                .ok_or_else(|| SweepError::Descriptor(format!("invalid child number: {}", step)))?;
+            components.push(Value::Integer(index.into()));
+        }
+        components.push(Value::Bool(hardened));
+    }
+    Ok(components)
+}
+
+fn coin_info_to_cbor(network: Network) -> Option<Value> {
+    // mainnet is the default and is omitted. crypto-coin-info has no regtest, which is encoded
+    // as testnet, so a regtest address is decoded as a testnet one with the same script
+    if network == Network::Bitcoin {
+        return None;
+    }
+    let mut map = BTreeMap::new();
+    map.insert(
+        Value::Integer(2),
+        Value::Integer(CborNetwork::Testnet as i128),
+    );
+    Some(Value::Tag(305, Box::new(Value::Map(map))))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
 pub enum CborNetwork {
     Mainnet = 0,
     Testnet = 1,
@@ -64,13 +354,18 @@ impl<'a, 'de> Deserialize<'de> for CryptoKeyPath2 {
             components: "".to_string(),
         };
 
-        // TODO: uint31 = uint32 .lt 0x80000000
-        if let Value::Tag(_number, val_nxt) = tagged {
-            // TODO assert number
+        if let Value::Tag(number, val_nxt) = tagged {
+            if number != 304 {
+                return Err(serde::de::Error::custom(format!(
+                    "crypto-keypath: unexpected tag {}",
+                    number
+                )));
+            }
             if let Value::Map(m) = *val_nxt {
                 let arr = m.get(&Value::Integer(1)).unwrap_or(&Value::Integer(0)); // this will skip parsing array in the next step
                 if let Value::Array(a) = arr {
-                    for i in 0..a.len() - 1 {
+                    check_keypath_components(a)?;
+                    for i in 0..a.len().saturating_sub(1) {
                         if let Value::Integer(ar) = a[i] {
                             obj.components.push_str(&format!("/{}", ar));
                             if a[i + 1] == Value::Bool(true) {
@@ -85,11 +380,45 @@ impl<'a, 'de> Deserialize<'de> for CryptoKeyPath2 {
                     }
                 }
             }
+        } else {
+            return Err(serde::de::Error::custom("crypto-keypath: missing tag 304"));
         }
         Ok(obj)
     }
 }
 
+/// Make sure the components of a crypto-keypath come in pairs of a child number (or a wildcard)
+/// and a hardened flag
+fn check_keypath_components<E: serde::de::Error>(a: &[Value]) -> Result<(), E> {
+    if a.len() % 2 != 0 {
+        return Err(E::custom(
+            "crypto-keypath: components must be pairs of a child number and a hardened flag",
+        ));
+    }
+    for (i, pair) in a.chunks(2).enumerate() {
+        let valid_index = match &pair[0] {
+            // uint31 = uint32 .lt 0x80000000
+            Value::Integer(n) => (0..0x8000_0000).contains(n),
+            // ranges are not supported
+            Value::Array(w) => w.is_empty(),
+            _ => false,
+        };
+        if !valid_index {
+            return Err(E::custom(format!(
+                "crypto-keypath: invalid child number in component {}",
+                i
+            )));
+        }
+        if !matches!(pair[1], Value::Bool(_)) {
+            return Err(E::custom(format!(
+                "crypto-keypath: missing hardened flag in component {}",
+                i
+            )));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, PartialEq)]
 pub struct CryptoKeyPath {
     pub components: Vec<bdk::bitcoin::util::bip32::ChildNumber>,
@@ -115,14 +444,25 @@ impl<'a, 'de> Deserialize<'de> for CryptoKeyPath {
             components_str: "".to_string(),
         };
 
-        // TODO: uint31 = uint32 .lt 0x80000000
-        if let Value::Tag(_number, val_nxt) = tagged {
-            // TODO assert number
+        if let Value::Tag(number, val_nxt) = tagged {
+            if number != 304 {
+                return Err(serde::de::Error::custom(format!(
+                    "crypto-keypath: unexpected tag {}",
+                    number
+                )));
+            }
             if let Value::Map(m) = *val_nxt {
                 let arr = m.get(&Value::Integer(1)).unwrap_or(&Value::Integer(0)); // this will skip parsing array in the next step
                 if let Value::Array(a) = arr {
-                    for i in 0..a.len() - 1 {
-                        // TODO 0 to -1 if len == 0 error
+                    check_keypath_components(a)?;
+                    // the fingerprint is needed to prefix the origin in the loop below
+                    let source_fingerprint = m.get(&Value::Integer(2));
+                    if let Some(Value::Integer(s)) = source_fingerprint {
+                        obj.source_fingerprint = u32::try_from(*s).map_err(|_| {
+                            serde::de::Error::custom("crypto-keypath: invalid source fingerprint")
+                        })?;
+                    }
+                    for i in 0..a.len().saturating_sub(1) {
                         if i == 0 {
                             if obj.source_fingerprint != 0 {
                                 obj.components_str
@@ -174,14 +514,12 @@ impl<'a, 'de> Deserialize<'de> for CryptoKeyPath {
                             obj.components_str = "".to_string();
                         }
                     }
-                    let source_fingerprint = m.get(&Value::Integer(2));
-                    if let Some(Value::Integer(s)) = source_fingerprint {
-                        obj.source_fingerprint = *s as u32;
-                    }
                     let depth = m.get(&Value::Integer(3));
                     if let Some(Value::Integer(s)) = depth {
                         // depth always takes precedense over components length
-                        obj.depth = *s as u8;
+                        obj.depth = u8::try_from(*s).map_err(|_| {
+                            serde::de::Error::custom("crypto-keypath: invalid depth")
+                        })?;
 
                         if obj.depth < obj.components.len() as u8 {
                             return Err(serde::de::Error::custom(
@@ -193,6 +531,8 @@ impl<'a, 'de> Deserialize<'de> for CryptoKeyPath {
                     }
                 }
             }
+        } else {
+            return Err(serde::de::Error::custom("crypto-keypath: missing tag 304"));
         }
         Ok(obj)
     }
@@ -208,56 +548,47 @@ pub struct EcKey {
 impl<'a> Deserialize<'a> for EcKey {
     fn deserialize<D: serde::de::Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
         // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-008-eckey.md
-        // Note: hashmap could have bytestrings as values, but here integer works according to cryptoconinfo spec
-        //let tagged = Tagged::<HashMap<u8, &[u8]>>::deserialize(deserializer)?;
         let tagged = Value::deserialize(deserializer)?;
 
-        let mut obj = EcKey {
-            curve: None,
-            is_private: None,
-            data: Vec::new(),
+        let m = match tagged {
+            Value::Tag(306, val) => match *val {
+                Value::Map(m) => m,
+                _ => return Err(serde::de::Error::custom("crypto-eckey: expected a map")),
+            },
+            Value::Map(m) => m,
+            _ => return Err(serde::de::Error::custom("crypto-eckey: expected a map")),
         };
 
-        if let Value::Tag(_number, val_nxt) = tagged {
-            if let Value::Map(m) = *val_nxt {
-                // TODO: check for 1 and 2 integers
-                let data = m
-                    .get(&Value::Integer(3))
-                    .ok_or_else(|| serde::de::Error::custom("EcKey: missing data"))?;
-                if let Value::Bytes(b) = data.clone() {
-                    obj.data = b;
-                }
+        let curve = match m.get(&Value::Integer(1)) {
+            // secp256k1
+            Some(Value::Integer(0)) => Some(0),
+            None => None,
+            Some(curve) => {
+                return Err(serde::de::Error::custom(format!(
+                    "crypto-eckey: unsupported curve {:?}, expected secp256k1 (0)",
+                    curve
+                )))
             }
-            Ok(obj)
-        } else {
-            Ok(obj)
-        }
-
-        /*
-        match tagged.tag {
-            Some(306) | None => {
-                if let Some(curve) = tagged.value.get(&1) {
-                    let num = u32::from_be_bytes(*pop(*curve));
-                    obj.curve = Some(num);
-                }
-
-                if let Some(is_private) = tagged.value.get(&2) {
-                    let is_private: bool = serde_cbor::de::from_slice(&is_private).unwrap();
-                    obj.is_private = Some(is_private);
-                }
-
-                if let Some(data) = tagged.value.get(&3) {
-                    println!("data {:?}", data);
-                    //let data: &[u8] = serde_cbor::de::from_slice(&data).unwrap();
-                    obj.data = data;
-                }
-
-                println!("* * * {:?}", tagged.value);
-
-                Ok(obj)
+        };
+        let is_private = match m.get(&Value::Integer(2)) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        };
+        let data = match m.get(&Value::Integer(3)) {
+            Some(Value::Bytes(b)) => b.clone(),
+            Some(_) => {
+                return Err(serde::de::Error::custom(
+                    "crypto-eckey: data is not a byte string",
+                ))
             }
-            Some(_) => Err(serde::de::Error::custom("unexpected tag")),
-        } */
+            None => return Err(serde::de::Error::custom("crypto-eckey: missing data")),
+        };
+
+        Ok(EcKey {
+            curve,
+            is_private,
+            data,
+        })
     }
 }
 
@@ -343,21 +674,14 @@ pub struct CborAddress<'a> {
 }
 
 pub fn is_ur_address(ur: String) -> bool {
-    ur.contains("ur:crypto-address")
+    ur.to_lowercase().starts_with("ur:crypto-address/")
 }
 
-pub fn _decode_ur_address(ur: String) -> Result<bdk::bitcoin::Address, SweepError> {
-    let (_key, val) = ur
-        .split_once(':')
-        .ok_or_else(|| SweepError::new("ur address".to_string(), "missing :".to_string()))?;
-    let (_key, val) = val
-        .split_once('/')
-        .ok_or_else(|| SweepError::new("ur address".to_string(), "missing /".to_string()))?;
-    let mut cbor = bytewords::decode(&val, &bytewords::Style::Minimal)?;
-    let cbor: CborAddress = serde_cbor::de::from_mut_slice(&mut cbor[..])?;
-    let data = cbor.data.to_vec(); // pubkeyhash
-
-    //println!("**data: {:?}", data);
+pub fn decode_ur_address(ur: String) -> Result<bdk::bitcoin::Address, SweepError> {
+    let mut cbor = split_ur_as(&ur, "crypto-address")?;
+    let cbor: CborAddress = serde_cbor::de::from_mut_slice(&mut cbor[..])
+        .map_err(|e| ur_error(format!("crypto-address: malformed CBOR ({})", e)))?;
+    let data = cbor.data.to_vec();
 
     let network = if let Some(info) = cbor.info {
         if let Some(n) = info.network {
@@ -372,144 +696,612 @@ pub fn _decode_ur_address(ur: String) -> Result<bdk::bitcoin::Address, SweepErro
         bdk::bitcoin::Network::Bitcoin
     };
 
-    // Try all possible payloads:
-    /*
-        // pkh
-        let tmp1 = bdk::bitcoin::util::address::Payload::PubkeyHash(
-            bdk::bitcoin::hash_types::PubkeyHash::from_slice(&data).unwrap(),
-        );
-
-        // p2sh
-        let tmp2 = bdk::bitcoin::util::address::Payload::ScriptHash(
-            bdk::bitcoin::hash_types::ScriptHash::from_slice(&data).unwrap(),
-        );
-    */
+    let data_len = data.len();
+    let wrong_length = move |_| {
+        SweepError::Ur(format!(
+            "crypto-address: unexpected data length: {}",
+            data_len
+        ))
+    };
 
-    Ok(bdk::bitcoin::Address {
-        payload: bdk::bitcoin::util::address::Payload::PubkeyHash(
-            bdk::bitcoin::hash_types::PubkeyHash::from_slice(&data).unwrap(),
+    let payload = match (cbor.type_, data_len) {
+        (Some(0), _) | (None, 20) => Payload::PubkeyHash(
+            bdk::bitcoin::hash_types::PubkeyHash::from_slice(&data).map_err(wrong_length)?,
+        ),
+        (Some(1), _) => Payload::ScriptHash(
+            bdk::bitcoin::hash_types::ScriptHash::from_slice(&data).map_err(wrong_length)?,
         ),
-        network,
+        (Some(2), 20) | (None, 32) => Payload::WitnessProgram {
+            version: bdk::bitcoin::bech32::u5::try_from_u8(0).unwrap(), // safe
+            program: data,
+        },
+        (t, len) => {
+            return Err(SweepError::Ur(format!(
+                "crypto-address: unsupported address type {:?} with data length {}",
+                t, len
+            )))
+        }
+    };
+
+    Ok(bdk::bitcoin::Address { payload, network })
+}
+
+/// A UR of any supported type, decoded for inspection
+#[derive(Debug, Serialize)]
+pub struct DecodedUr {
+    #[serde(rename = "type")]
+    pub ur_type: String,
+    pub cbor: serde_json::Value,
+    pub core: serde_json::Value,
+}
+
+fn ur_error(message: String) -> SweepError {
+    SweepError::Ur(message)
+}
+
+/// Split a UR into its type and its CBOR payload, checking the bytewords checksum
+fn split_ur(ur: &str) -> Result<(String, Vec<u8>), SweepError> {
+    let ur = ur.trim();
+    // QR codes usually carry URs in upper case, but a mix of both is a typo
+    if ur != ur.to_lowercase() && ur != ur.to_uppercase() {
+        return Err(ur_error("mixes upper and lower case".to_string()));
+    }
+    let ur = ur.to_lowercase();
+    let (scheme, val) = ur
+        .split_once(':')
+        .ok_or_else(|| ur_error("missing \"ur:\" prefix".to_string()))?;
+    if scheme != "ur" {
+        return Err(ur_error(format!("unexpected scheme \"{}\"", scheme)));
+    }
+    let (ur_type, val) = val
+        .split_once('/')
+        .ok_or_else(|| ur_error("missing / after the type".to_string()))?;
+    if ur_type.is_empty()
+        || !ur_type
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(ur_error(format!("invalid type \"{}\"", ur_type)));
+    }
+    if val.contains('/') {
+        return Err(ur_error(format!(
+            "{}: multi-part URs are not supported, scan all parts first",
+            ur_type
+        )));
+    }
+    let cbor = bytewords::decode(&val, &bytewords::Style::Minimal).map_err(|e| {
+        ur_error(format!(
+            "{}: invalid bytewords or checksum mismatch ({}). Check for a misread QR code",
+            ur_type, e
+        ))
+    })?;
+    Ok((ur_type.to_string(), cbor))
+}
+
+/// Split a UR, making sure it has the expected type
+fn split_ur_as(ur: &str, expected: &str) -> Result<Vec<u8>, SweepError> {
+    let (ur_type, cbor) = split_ur(ur)?;
+    if ur_type != expected {
+        return Err(ur_error(format!("expected {}, got {}", expected, ur_type)));
+    }
+    Ok(cbor)
+}
+
+/// Parse the CBOR payload of a UR, which has to be a single well-formed item
+fn cbor_value(ur_type: &str, cbor: &[u8]) -> Result<Value, SweepError> {
+    serde_cbor::from_slice(cbor)
+        .map_err(|e| ur_error(format!("{}: malformed CBOR ({})", ur_type, e)))
+}
+
+/// Decode any supported UR and convert it to its Bitcoin Core compatible representation.
+/// `network` is only used for keys which don't carry one, i.e. crypto-eckey and crypto-seed.
+pub fn decode_ur(ur: &str, network: Network) -> Result<DecodedUr, SweepError> {
+    let (ur_type, cbor) = split_ur(ur)?;
+    let data = cbor_value(&ur_type, &cbor)?;
+
+    let core = match ur_type.as_str() {
+        "crypto-output" => {
+            let mut out = String::new();
+            parse_ur_desc(data.clone(), network, &mut out)?;
+            serde_json::Value::String(out)
+        }
+        "crypto-hdkey" => {
+            let mut out = String::new();
+            parse_ur_hdkey(&data, &mut out)?;
+            serde_json::Value::String(out)
+        }
+        "crypto-eckey" => {
+            let mut out = String::new();
+            parse_ur_eckey(&Value::Tag(306, Box::new(data.clone())), network, &mut out)?;
+            serde_json::Value::String(out)
+        }
+        "crypto-address" => {
+            serde_json::Value::String(decode_ur_address(ur.to_string())?.to_string())
+        }
+        "crypto-psbt" => {
+            if let Value::Bytes(ref psbt) = data {
+                // make sure this is a valid PSBT
+                let _psbt: bdk::bitcoin::util::psbt::PartiallySignedTransaction =
+                    bdk::bitcoin::consensus::deserialize(psbt)?;
+                serde_json::Value::String(base64::encode(psbt))
+            } else {
+                return Err(SweepError::Ur(
+                    "crypto-psbt: expected a byte string".to_string(),
+                ));
+            }
+        }
+        "crypto-account" => {
+            let (_fingerprint, descriptors) = parse_ur_account(&data, network)?;
+            serde_json::Value::from(descriptors)
+        }
+        "crypto-seed" => serde_json::Value::String(parse_ur_seed(&data, network)?.to_string()),
+        _ => return Err(SweepError::Ur(format!("unsupported UR type: {}", ur_type))),
+    };
+
+    Ok(DecodedUr {
+        ur_type,
+        cbor: cbor_to_json(&data),
+        core,
     })
 }
 
+/// Parse an untagged crypto-account into its master fingerprint and output descriptors
+pub fn parse_ur_account(val: &Value, network: Network) -> Result<(u32, Vec<String>), SweepError> {
+    // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-015-account.md
+    let err = |msg: &str| SweepError::Ur(format!("crypto-account: {}", msg));
+
+    let m = if let Value::Map(m) = val {
+        m
+    } else {
+        return Err(err("expected a map"));
+    };
+
+    let fingerprint = match m.get(&Value::Integer(1)) {
+        Some(Value::Integer(f)) => {
+            u32::try_from(*f).map_err(|_| err("invalid master fingerprint"))?
+        }
+        _ => return Err(err("missing master fingerprint")),
+    };
+
+    let outputs = match m.get(&Value::Integer(2)) {
+        Some(Value::Array(a)) if !a.is_empty() => a,
+        _ => return Err(err("missing output descriptors")),
+    };
+
+    let mut descriptors = Vec::new();
+    for output in outputs {
+        let output = match output {
+            // crypto-output
+            Value::Tag(308, desc) => *desc.clone(),
+            _ => output.clone(),
+        };
+        let mut out = String::new();
+        parse_ur_desc(output, network, &mut out)?;
+        descriptors.push(out);
+    }
+
+    Ok((fingerprint, descriptors))
+}
+
+pub fn is_ur_account(ur: String) -> bool {
+    ur.to_lowercase().starts_with("ur:crypto-account/")
+}
+
+/// Expand a crypto-account into pairs of (receive, change) descriptors.
+/// Keys without a derivation path for children get `/0/*` and `/1/*` respectively and
+/// keys deriving from `/0/*` get `/1/*` for change. Any other children are an error.
+pub fn parse_ur_account_descriptors(
+    ur: String,
+    network: Network,
+) -> Result<Vec<(String, String)>, SweepError> {
+    let cbor = split_ur_as(&ur, "crypto-account")?;
+    let data = cbor_value("crypto-account", &cbor)?;
+
+    let outputs = match data {
+        Value::Map(ref m) => match m.get(&Value::Integer(2)) {
+            Some(Value::Array(a)) => a.clone(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new(),
+    };
+    // make sure the account is well formed
+    parse_ur_account(&data, network)?;
+
+    let mut descriptors = Vec::new();
+    for output in outputs {
+        let output = match output {
+            // crypto-output
+            Value::Tag(308, desc) => *desc,
+            _ => output,
+        };
+
+        let unsupported = || {
+            let mut desc = String::new();
+            match parse_ur_desc(output.clone(), network, &mut desc) {
+                Ok(()) => SweepError::Ur(format!(
+                    "no change descriptor for {} of the crypto-account, only keys without children or deriving from /0 are supported",
+                    desc
+                )),
+                Err(e) => e,
+            }
+        };
+        let mut desc = String::new();
+        parse_ur_desc(
+            with_children(&output, false).ok_or_else(unsupported)?,
+            network,
+            &mut desc,
+        )?;
+        let mut desc_chg = String::new();
+        parse_ur_desc(
+            with_children(&output, true).ok_or_else(unsupported)?,
+            network,
+            &mut desc_chg,
+        )?;
+
+        descriptors.push((desc, desc_chg));
+    }
+
+    Ok(descriptors)
+}
+
+/// Set the children derivation path of every crypto-hdkey in `val` to `/0/*` or `/1/*`.
+/// `None` if a key derives its children from anything but `/0`, as there is no change
+/// descriptor to tell from its receive descriptor then.
+fn with_children(val: &Value, change: bool) -> Option<Value> {
+    match val {
+        Value::Tag(303, hdkey) => {
+            let mut hdkey = *hdkey.clone();
+            if let Value::Map(ref mut m) = hdkey {
+                let children = m.entry(Value::Integer(7)).or_insert_with(|| {
+                    let mut keypath = BTreeMap::new();
+                    keypath.insert(
+                        Value::Integer(1),
+                        Value::Array(vec![
+                            Value::Integer(0),
+                            Value::Bool(false),
+                            Value::Array(Vec::new()),
+                            Value::Bool(false),
+                        ]),
+                    );
+                    Value::Tag(304, Box::new(Value::Map(keypath)))
+                });
+                if let Value::Tag(_, keypath) = children {
+                    if let Value::Map(ref mut keypath) = **keypath {
+                        if let Some(Value::Array(components)) = keypath.get_mut(&Value::Integer(1))
+                        {
+                            if components.first() != Some(&Value::Integer(0)) {
+                                return None;
+                            }
+                            if change {
+                                components[0] = Value::Integer(1);
+                            }
+                        }
+                    }
+                }
+            }
+            Some(Value::Tag(303, Box::new(hdkey)))
+        }
+        Value::Tag(tag, v) => Some(Value::Tag(*tag, Box::new(with_children(v, change)?))),
+        Value::Map(m) => m
+            .iter()
+            .map(|(k, v)| Some((k.clone(), with_children(v, change)?)))
+            .collect::<Option<_>>()
+            .map(Value::Map),
+        Value::Array(a) => a
+            .iter()
+            .map(|v| with_children(v, change))
+            .collect::<Option<_>>()
+            .map(Value::Array),
+        _ => Some(val.clone()),
+    }
+}
+
+/// Parse an untagged crypto-seed into a master private key.
+/// The seed payload is BIP39 entropy as exported by e.g. Gordian Seed Tool.
+pub fn parse_ur_seed(val: &Value, network: Network) -> Result<ExtendedPrivKey, SweepError> {
+    // spec: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-006-urtypes.md#cryptographic-seed-crypto-seed
+    let payload = match val {
+        Value::Map(m) => match m.get(&Value::Integer(1)) {
+            Some(Value::Bytes(b)) => b,
+            _ => return Err(SweepError::Ur("crypto-seed: missing payload".to_string())),
+        },
+        _ => return Err(SweepError::Ur("crypto-seed: expected a map".to_string())),
+    };
+
+    let mnemonic = bip39::Mnemonic::from_entropy(payload, bip39::Language::English)
+        .map_err(|e| SweepError::Ur(format!("crypto-seed: {}", e)))?;
+    let seed = bip39::Seed::new(&mnemonic, "");
+
+    Ok(ExtendedPrivKey::new_master(network, seed.as_bytes())?)
+}
+
+/// Parse a tagged crypto-eckey and append it to `out`
+fn parse_ur_eckey(val: &Value, network: Network, out: &mut String) -> Result<(), SweepError> {
+    let p = serde_cbor::to_vec(val)?;
+    let eckey: EcKey = serde_cbor::de::from_slice(&p)?;
+    if eckey.is_private == Some(true) {
+        let privkey = PrivateKey {
+            compressed: true,
+            network,
+            key: bdk::bitcoin::secp256k1::SecretKey::from_slice(&eckey.data)?,
+        };
+        out.push_str(&privkey.to_wif());
+    } else {
+        out.push_str(&PublicKey::from_slice(&eckey.data)?.to_string());
+    }
+    Ok(())
+}
+
+/// Convert a CBOR tree to JSON, so it can be inspected
+fn cbor_to_json(val: &Value) -> serde_json::Value {
+    use serde_json::json;
+    match val {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => json!(b),
+        Value::Integer(i) => i64::try_from(*i)
+            .map(|i| json!(i))
+            .unwrap_or_else(|_| json!(i.to_string())),
+        Value::Float(f) => json!(f),
+        // byte strings are shown as hex
+        Value::Bytes(b) => json!(hex::encode(b)),
+        Value::Text(t) => json!(t),
+        Value::Array(a) => serde_json::Value::Array(a.iter().map(cbor_to_json).collect()),
+        Value::Map(m) => serde_json::Value::Object(
+            m.iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Value::Integer(i) => i.to_string(),
+                        Value::Text(t) => t.clone(),
+                        _ => format!("{:?}", k),
+                    };
+                    (key, cbor_to_json(v))
+                })
+                .collect(),
+        ),
+        Value::Tag(tag, v) => json!({"tag": tag, "value": cbor_to_json(v)}),
+        _ => serde_json::Value::Null,
+    }
+}
+
+pub fn is_ur_signing_key(ur: &str) -> bool {
+    let ur = ur.to_lowercase();
+    ur.starts_with("ur:crypto-hdkey/") || ur.starts_with("ur:crypto-seed/")
+}
+
 pub fn is_ur_descriptor(ur: String) -> bool {
-    ur.starts_with("ur:crypto-output/")
+    ur.to_lowercase().starts_with("ur:crypto-output/")
 }
 
-pub fn parse_ur_descriptor(ur: String) -> Result<String, SweepError> {
-    let (_key, val) = ur.split_once(':').unwrap(); // safe
-    let (_key, val) = val.split_once('/').unwrap(); // safe
-    let cbor = bytewords::decode(&val, &bytewords::Style::Minimal)?;
+pub fn parse_ur_descriptor(ur: String, network: Network) -> Result<String, SweepError> {
+    let cbor = split_ur_as(&ur, "crypto-output")?;
 
-    let data: Value = serde_cbor::from_slice(&cbor)?;
+    let data = cbor_value("crypto-output", &cbor)?;
     let mut ur_out = String::new();
-    parse_ur_desc(data, &mut ur_out)?;
+    parse_ur_desc(data, network, &mut ur_out)?;
     Ok(ur_out)
 }
 
-pub fn parse_ur_desc(val: Value, out: &mut String) -> Result<Box<Value>, SweepError> {
-    if let Value::Tag(number, mut val_nxt) = val.clone() {
-        match number {
-            303 => {
-                let p = serde_cbor::to_vec(&val_nxt).unwrap();
-                let hdkey: HDKey = serde_cbor::de::from_slice(&p[..])?;
-                //println!("debug: hdkey: {:?}", hdkey);
-
-                // TODO check if this is master key-> no need for dealing with with derivpath if yes
-                // TODO implement iterators to use and_then
-                let net = if let Some(info) = hdkey.use_info {
-                    if let Some(n) = info.network {
-                        n
-                    } else {
-                        CborNetwork::Mainnet
-                    }
-                } else {
-                    CborNetwork::Mainnet
-                };
+/// Extended key of a crypto-hdkey
+enum XKey {
+    Public(ExtendedPubKey),
+    Private(ExtendedPrivKey),
+}
 
-                let keydata = &hdkey.key_data[..].to_vec();
+fn hdkey_to_xkey(hdkey: &HDKey) -> Result<XKey, SweepError> {
+    // TODO check if this is master key-> no need for dealing with with derivpath if yes
+    let net = if let Some(ref info) = hdkey.use_info {
+        if let Some(n) = info.network {
+            n
+        } else {
+            CborNetwork::Mainnet
+        }
+    } else {
+        CborNetwork::Mainnet
+    };
+    let network = bdk::bitcoin::Network::try_from(net)
+        .map_err(|_| SweepError::Ur("crypto-hdkey: wrong network".to_string()))?;
+
+    let childnumber = if let Some(ref origin) = hdkey.origin {
+        *origin
+            .components
+            .last()
+            .unwrap_or(&bdk::bitcoin::util::bip32::ChildNumber::from(0))
+    } else {
+        bdk::bitcoin::util::bip32::ChildNumber::from(0)
+    };
 
-                let childnumber = if let Some(ref origin) = hdkey.origin {
-                    *origin
-                        .components
-                        .last()
-                        .unwrap_or(&bdk::bitcoin::util::bip32::ChildNumber::from(0))
-                } else {
-                    bdk::bitcoin::util::bip32::ChildNumber::from(0)
-                };
+    let depth = if let Some(ref d) = hdkey.origin {
+        d.depth
+    } else {
+        0
+    };
 
-                let depth = if let Some(ref d) = hdkey.origin {
-                    d.depth
-                } else {
-                    0
-                };
+    let parent_fingerprint = if let Some(ref origin) = hdkey.origin {
+        let l = origin.components.len();
+        if l == 1 && origin.source_fingerprint != 0 {
+            // If `origin` contains only a single derivation step and also contains `source-fingerprint`,
+            // then `parent-fingerprint` MUST be identical to `source-fingerprint` or may be omitted.
+            origin.source_fingerprint
+        } else {
+            hdkey.parent_fingerprint.unwrap_or(0)
+        }
+    } else {
+        hdkey.parent_fingerprint.unwrap_or(0)
+    };
+    let parent_fingerprint = Fingerprint::from(&parent_fingerprint.to_be_bytes()[..]);
+
+    let chain_code = hdkey
+        .chain_code
+        .ok_or_else(|| SweepError::Ur("crypto-hdkey: missing chain code".to_string()))?;
+    if chain_code.len() != 32 {
+        return Err(SweepError::Ur(format!(
+            "crypto-hdkey: chain code must be 32 bytes, got {}",
+            chain_code.len()
+        )));
+    }
+    let chain_code = ChainCode::from(chain_code);
+
+    if hdkey.is_private == Some(true) {
+        // private key data is prefixed with 0x00 to make it 33 bytes long
+        if hdkey.key_data.len() != 33 || hdkey.key_data[0] != 0 {
+            return Err(SweepError::Ur(
+                "crypto-hdkey: invalid private key data".to_string(),
+            ));
+        }
+        Ok(XKey::Private(ExtendedPrivKey {
+            network,
+            depth,
+            parent_fingerprint,
+            child_number: childnumber,
+            private_key: PrivateKey {
+                compressed: true,
+                network,
+                key: bdk::bitcoin::secp256k1::SecretKey::from_slice(&hdkey.key_data[1..])?,
+            },
+            chain_code,
+        }))
+    } else {
+        Ok(XKey::Public(ExtendedPubKey {
+            network,
+            depth,
+            parent_fingerprint,
+            child_number: childnumber,
+            public_key: PublicKey::from_slice(hdkey.key_data)?,
+            chain_code,
+        }))
+    }
+}
 
-                let parent_fingerprint = if let Some(ref origin) = hdkey.origin {
-                    let l = origin.components.len();
-                    if l == 1 && origin.source_fingerprint != 0 {
-                        // If `origin` contains only a single derivation step and also contains `source-fingerprint`,
-                        // then `parent-fingerprint` MUST be identical to `source-fingerprint` or may be omitted.
-                        origin.source_fingerprint
-                    } else {
-                        hdkey.parent_fingerprint.unwrap_or(0)
-                    }
-                } else {
-                    hdkey.parent_fingerprint.unwrap_or(0)
-                };
+/// Parse an untagged crypto-hdkey and append it to `out` as a key expression
+pub fn parse_ur_hdkey(val: &Value, out: &mut String) -> Result<(), SweepError> {
+    let p = serde_cbor::to_vec(val)?;
+    let hdkey: HDKey = serde_cbor::de::from_slice(&p[..])?;
 
-                let xpub = bdk::bitcoin::util::bip32::ExtendedPubKey {
-                    network: bdk::bitcoin::Network::try_from(net).map_err(|_| {
-                        SweepError::new("xpub".to_string(), "wrong network".to_string())
-                    })?,
-                    depth,
-                    parent_fingerprint: bdk::bitcoin::util::bip32::Fingerprint::from(
-                        &parent_fingerprint.to_be_bytes()[..],
-                    ),
-                    child_number: childnumber,
-                    public_key: bdk::bitcoin::PublicKey::from_slice(&keydata[..])?,
-                    chain_code: bdk::bitcoin::util::bip32::ChainCode::from(
-                        hdkey.chain_code.ok_or_else(|| {
-                            SweepError::new("hdkey".to_string(), "chaincode".to_string())
-                        })?,
-                    ),
-                };
+    let xkey = hdkey_to_xkey(&hdkey)?;
 
-                //println!("debug xpub>>: {:?}", xpub);
+    if let Some(ref c) = hdkey.origin {
+        out.push_str(&c.components_str);
+    };
 
-                if let Some(c) = hdkey.origin {
-                    out.push_str(&c.components_str);
-                };
+    match xkey {
+        XKey::Public(xpub) => out.push_str(&xpub.to_string()),
+        XKey::Private(xprv) => out.push_str(&xprv.to_string()),
+    }
 
-                out.push_str(&xpub.to_string());
+    if let Some(c) = hdkey.children {
+        out.push_str(&c.components);
+    };
 
-                if let Some(c) = hdkey.children {
-                    out.push_str(&c.components);
-                };
+    Ok(())
+}
+
+/// Parse a private crypto-hdkey or a crypto-seed UR into an extended private key and
+/// its origin, if known
+pub fn parse_ur_signing_key(
+    ur: &str,
+    network: Network,
+) -> Result<(ExtendedPrivKey, Option<(Fingerprint, DerivationPath)>), SweepError> {
+    let (ur_type, cbor) = split_ur(ur)?;
+    let data = cbor_value(&ur_type, &cbor)?;
+
+    match ur_type.as_str() {
+        // a seed is a master key, so it's its own origin
+        "crypto-seed" => Ok((parse_ur_seed(&data, network)?, None)),
+        "crypto-hdkey" => {
+            let p = serde_cbor::to_vec(&data)?;
+            let hdkey: HDKey = serde_cbor::de::from_slice(&p[..])?;
+            match hdkey_to_xkey(&hdkey)? {
+                XKey::Private(xprv) => {
+                    let origin = hdkey.origin.filter(|o| o.source_fingerprint != 0).map(|o| {
+                        (
+                            Fingerprint::from(&o.source_fingerprint.to_be_bytes()[..]),
+                            DerivationPath::from(o.components),
+                        )
+                    });
+                    Ok((xprv, origin))
+                }
+                XKey::Public(_) => Err(SweepError::Ur(
+                    "crypto-hdkey: not a private key".to_string(),
+                )),
+            }
+        }
+        _ => Err(SweepError::Ur(format!(
+            "expected crypto-hdkey or crypto-seed, got {}",
+            ur_type
+        ))),
+    }
+}
+
+/// Tags allowed directly inside a script expression, `None` being the top level
+fn allowed_tags(parent: Option<u64>) -> &'static [u64] {
+    match parent {
+        None => &[400, 401, 402, 403, 404, 406, 407],
+        // sh
+        Some(400) => &[401, 402, 403, 404, 406, 407],
+        // wsh
+        Some(401) => &[402, 403, 406, 407],
+        // pk, pkh, wpkh, multi and sortedmulti take keys
+        _ => &[303, 306],
+    }
+}
+
+/// Error pointing at the part of the descriptor parsed so far
+fn desc_error(out: &str, message: &str) -> SweepError {
+    if out.is_empty() {
+        SweepError::Ur(format!("crypto-output: {}", message))
+    } else {
+        SweepError::Ur(format!("crypto-output: {} after \"{}\"", message, out))
+    }
+}
+
+pub fn parse_ur_desc(
+    val: Value,
+    network: Network,
+    out: &mut String,
+) -> Result<Box<Value>, SweepError> {
+    parse_ur_script(val, network, out, None)
+}
+
+fn parse_ur_script(
+    val: Value,
+    network: Network,
+    out: &mut String,
+    parent: Option<u64>,
+) -> Result<Box<Value>, SweepError> {
+    if let Value::Tag(number, mut val_nxt) = val.clone() {
+        if !allowed_tags(parent).contains(&number) {
+            return Err(desc_error(out, &format!("unexpected tag {}", number)));
+        }
+        match number {
+            303 => {
+                parse_ur_hdkey(&val_nxt, out)?;
             }
             306 => {
-                let p = serde_cbor::to_vec(&val).unwrap();
-                let eckey: EcKey = serde_cbor::de::from_slice(&p)?;
-                out.push_str(&hex::encode(eckey.data));
+                parse_ur_eckey(&val, network, out)?;
             }
             400 => {
                 out.push_str(&"sh(".to_string());
-                val_nxt = parse_ur_desc(*val_nxt, out)?;
+                val_nxt = parse_ur_script(*val_nxt, network, out, Some(number))?;
+                out.push_str(&")".to_string());
+            }
+            402 => {
+                out.push_str(&"pk(".to_string());
+                val_nxt = parse_ur_script(*val_nxt, network, out, Some(number))?;
                 out.push_str(&")".to_string());
             }
             403 => {
                 out.push_str(&"pkh(".to_string());
-                val_nxt = parse_ur_desc(*val_nxt, out).unwrap();
+                val_nxt = parse_ur_script(*val_nxt, network, out, Some(number))?;
                 out.push_str(&")".to_string());
             }
             401 => {
                 out.push_str(&"wsh(".to_string());
-                val_nxt = parse_ur_desc(*val_nxt, out).unwrap();
+                val_nxt = parse_ur_script(*val_nxt, network, out, Some(number))?;
                 out.push_str(&")".to_string());
             }
             404 => {
                 out.push_str(&"wpkh(".to_string());
-                val_nxt = parse_ur_desc(*val_nxt, out)?;
+                val_nxt = parse_ur_script(*val_nxt, network, out, Some(number))?;
                 out.push_str(&")".to_string());
             }
             406 | 407 => {
@@ -519,50 +1311,37 @@ pub fn parse_ur_desc(val: Value, out: &mut String) -> Result<Box<Value>, SweepEr
                     out.push_str(&"sortedmulti(".to_string());
                 }
 
-                if let Value::Map(v) = *val_nxt.clone() {
-                    let threshold = v.get(&Value::Integer(1)).ok_or_else(|| {
-                        SweepError::new(
-                            "output descriptor".to_string(),
-                            "multi missing threshold".to_string(),
-                        )
-                    })?;
-                    if let Value::Integer(i) = threshold {
-                        out.push_str(&format!("{},", i));
-                    }
-                    let arr = v.get(&Value::Integer(2)).ok_or_else(|| {
-                        SweepError::new(
-                            "output descriptor".to_string(),
-                            "multi missing keys".to_string(),
-                        )
-                    })?;
-                    if let Value::Array(v) = arr {
-                        for i in v {
-                            if let Value::Tag(num, _) = i {
-                                if *num == 303 {
-                                    // hdkey
-                                    val_nxt = parse_ur_desc(i.clone(), out)?;
-                                } else if *num == 306 {
-                                    // eckey
-                                    let p = serde_cbor::to_vec(&i)?;
-                                    let eckey: EcKey = serde_cbor::de::from_slice(&p)?;
-                                    out.push_str(&hex::encode(eckey.data));
-                                }
-                                out.push(',');
-                            }
-                        }
-                        out.pop();
+                let v = if let Value::Map(v) = *val_nxt.clone() {
+                    v
+                } else {
+                    return Err(desc_error(out, "multi expects a map"));
+                };
+                let keys = match v.get(&Value::Integer(2)) {
+                    Some(Value::Array(keys)) if !keys.is_empty() => keys,
+                    _ => return Err(desc_error(out, "multi missing keys")),
+                };
+                match v.get(&Value::Integer(1)) {
+                    Some(Value::Integer(i)) if (1..=keys.len() as i128).contains(i) => {
+                        out.push_str(&format!("{},", i))
                     }
+                    Some(_) => return Err(desc_error(out, "multi threshold out of range")),
+                    None => return Err(desc_error(out, "multi missing threshold")),
                 }
+                for key in keys {
+                    val_nxt = parse_ur_script(key.clone(), network, out, Some(number))?;
+                    out.push(',');
+                }
+                out.pop();
                 out.push_str(&")".to_string());
             }
 
-            _ => panic!("wrong tag {:?}", number),
+            _ => unreachable!(), // checked by allowed_tags
         }
         Ok(val_nxt)
     } else {
-        Err(SweepError::new(
-            "UR output descriptor".to_string(),
-            "parsing".to_string(),
+        Err(desc_error(
+            out,
+            "expected a tagged script expression or key",
         ))
     }
 }
@@ -573,7 +1352,7 @@ fn outputdesc_test_vector_5() -> Result<(), SweepError> {
     let data: Value = serde_cbor::from_slice(&inp).unwrap();
     let expected = "wsh(multi(1,xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB/1/0/*,xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH/0/0/*))";
     let mut out = String::new();
-    parse_ur_desc(data, &mut out)?;
+    parse_ur_desc(data, Network::Bitcoin, &mut out)?;
     println!("\noutput descriptor: {:?}", out);
 
     assert_eq!(expected, out);
@@ -588,7 +1367,7 @@ fn outputdesc_test_vector_4() -> Result<(), SweepError> {
 
     let data: Value = serde_cbor::from_slice(&inp).unwrap();
     let mut out = String::new();
-    parse_ur_desc(data, &mut out)?;
+    parse_ur_desc(data, Network::Bitcoin, &mut out)?;
     println!("\noutput descriptor: {:?}", out);
 
     // TODO this test case is incorrect in the spec, it contains incorrect depth
@@ -602,7 +1381,7 @@ fn outputdesc_test_vector_3() -> Result<(), SweepError> {
     let expected = "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))";
     let data: Value = serde_cbor::from_slice(&inp).unwrap();
     let mut out = String::new();
-    parse_ur_desc(data, &mut out)?;
+    parse_ur_desc(data, Network::Bitcoin, &mut out)?;
 
     // This test vector is correct
     assert_eq!(out, expected);
@@ -610,6 +1389,381 @@ fn outputdesc_test_vector_3() -> Result<(), SweepError> {
     Ok(())
 }
 
+#[test]
+fn outputdesc_encode_test_vector_3() -> Result<(), SweepError> {
+    let inp = "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))";
+    let expected = "ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadremh";
+
+    assert_eq!(descriptor_as_ur(inp)?, expected);
+    assert_eq!(
+        parse_ur_descriptor(expected.to_string(), Network::Bitcoin)?,
+        inp
+    );
+
+    Ok(())
+}
+
+#[test]
+fn outputdesc_encode_round_trip() -> Result<(), SweepError> {
+    let inp = "wpkh([c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*)";
+
+    let ur = descriptor_as_ur(inp)?;
+    assert!(ur.starts_with("ur:crypto-output/"));
+    assert_eq!(parse_ur_descriptor(ur, Network::Testnet)?, inp);
+
+    // the checksum is dropped
+    let ur = descriptor_as_ur(&format!("{}#xxxxxxxx", inp))?;
+    assert_eq!(parse_ur_descriptor(ur, Network::Testnet)?, inp);
+
+    Ok(())
+}
+
+#[test]
+fn hdkey_encode_test() -> Result<(), SweepError> {
+    let inp = "[c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE";
+
+    let ur = hdkey_as_ur(inp)?;
+    assert!(ur.starts_with("ur:crypto-hdkey/"));
+
+    let (_key, val) = ur.split_once('/').unwrap();
+    let cbor = bytewords::decode(&val, &bytewords::Style::Minimal)?;
+    let hdkey: HDKey = serde_cbor::de::from_slice(&cbor)?;
+
+    assert_eq!(hdkey.is_private, None);
+    assert_eq!(hdkey.parent_fingerprint, Some(0xcf4054ec));
+    let origin = hdkey.origin.unwrap();
+    assert_eq!(origin.source_fingerprint, 0xc258d2e4);
+    assert_eq!(origin.depth, 3);
+    assert_eq!(origin.components_str, "[c258d2e4/84h/1h/0h]");
+
+    assert!(
+        hdkey_as_ur("02fc9e5af0ac8d9b3cecfe2a888e2117ba3d089d8585886c9c826b6b22a98d12ea").is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn address_encode_round_trip() -> Result<(), SweepError> {
+    for inp in &[
+        "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2",
+        "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy",
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+        "tb1qvctwrh8ckrex8daxya4xleaevcp299ttd9gnev",
+    ] {
+        let ur = address_as_ur(inp)?;
+        assert!(ur.starts_with("ur:crypto-address/"));
+        assert_eq!(decode_ur_address(ur)?.to_string(), *inp);
+    }
+
+    // regtest is encoded as testnet, the script is the same
+    let regtest = Address::from_str("bcrt1qvctwrh8ckrex8daxya4xleaevcp299tt0v37w9")?;
+    let decoded = decode_ur_address(address_as_ur(&regtest.to_string())?)?;
+    assert_eq!(
+        decoded.to_string(),
+        "tb1qvctwrh8ckrex8daxya4xleaevcp299ttd9gnev"
+    );
+    assert_eq!(decoded.script_pubkey(), regtest.script_pubkey());
+
+    Ok(())
+}
+
+#[test]
+fn decode_ur_test() -> Result<(), SweepError> {
+    let desc = "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))";
+    let ur = "ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadremh";
+
+    let decoded = decode_ur(ur, Network::Testnet)?;
+    assert_eq!(decoded.ur_type, "crypto-output");
+    assert_eq!(decoded.core, serde_json::json!(desc));
+    assert_eq!(decoded.cbor["tag"], 400);
+    assert_eq!(decoded.cbor["value"]["tag"], 406);
+    assert_eq!(decoded.cbor["value"]["value"]["1"], 2);
+
+    // URs scanned from QR codes are upper case
+    let decoded = decode_ur(&ur.to_uppercase(), Network::Testnet)?;
+    assert_eq!(decoded.core, serde_json::json!(desc));
+
+    let (_key, payload) = ur.split_once('/').unwrap();
+    assert!(decode_ur(&format!("ur:crypto-foo/{}", payload), Network::Testnet).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn decode_ur_psbt_test() -> Result<(), SweepError> {
+    let inp = hex::decode("70736274FF01009A020000000258E87A21B56DAF0C23BE8E7070456C336F7CBAA5C8757924F545887BB2ABDD750000000000FFFFFFFF838D0427D0EC650A68AA46BB0B098AEA4422C071B2CA78352A077959D07CEA1D0100000000FFFFFFFF0270AAF00800000000160014D85C2B71D0060B09C9886AEB815E50991DDA124D00E1F5050000000016001400AEA9A2E5F0F876A588DF5546E8742D1D87008F000000000000000000").unwrap();
+
+    let decoded = decode_ur(&psbt_as_ur(inp.clone())?, Network::Testnet)?;
+    assert_eq!(decoded.ur_type, "crypto-psbt");
+    assert_eq!(decoded.core, serde_json::json!(base64::encode(&inp)));
+
+    let ur = psbt_as_ur(inp.clone())?;
+    assert!(is_ur_psbt(&ur.to_uppercase()));
+    assert_eq!(parse_ur_psbt(&ur)?, inp);
+    assert!(parse_ur_psbt(&encode_as_ur("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")?).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn decode_ur_seed_test() -> Result<(), SweepError> {
+    // BIP39 entropy of "abandon abandon ... about"
+    let mut seed = BTreeMap::new();
+    seed.insert(Value::Integer(1), Value::Bytes(vec![0u8; 16]));
+    let ur = cbor_as_ur("crypto-seed", &Value::Map(seed))?;
+
+    let decoded = decode_ur(&ur, Network::Bitcoin)?;
+    assert_eq!(decoded.ur_type, "crypto-seed");
+    assert_eq!(decoded.core, serde_json::json!("xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu"));
+
+    let decoded = decode_ur(&ur, Network::Testnet)?;
+    assert_eq!(decoded.core, serde_json::json!("tprv8ZgxMBicQKsPe5YMU9gHen4Ez3ApihUfykaqUorj9t6FDqy3nP6eoXiAo2ssvpAjoLroQxHqr3R5nE3a5dU3DHTjTgJDd7zrbniJr6nrCzd"));
+
+    Ok(())
+}
+
+#[test]
+fn account_descriptors_test() -> Result<(), SweepError> {
+    let key = "[c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE";
+
+    // accounts exported by hardware wallets don't specify the children derivation path
+    let wpkh = Value::Tag(404, Box::new(key_to_cbor(key)?));
+    let pkh = Value::Tag(403, Box::new(key_to_cbor(&format!("{}/0/*", key))?));
+    let mut account = BTreeMap::new();
+    account.insert(Value::Integer(1), Value::Integer(0xc258d2e4));
+    account.insert(
+        Value::Integer(2),
+        Value::Array(vec![
+            Value::Tag(308, Box::new(wpkh)),
+            Value::Tag(308, Box::new(pkh)),
+        ]),
+    );
+    let ur = cbor_as_ur("crypto-account", &Value::Map(account))?;
+
+    assert!(is_ur_account(ur.clone()));
+    let descriptors = parse_ur_account_descriptors(ur.clone(), Network::Testnet)?;
+    assert_eq!(
+        descriptors,
+        vec![
+            (format!("wpkh({}/0/*)", key), format!("wpkh({}/1/*)", key)),
+            (format!("pkh({}/0/*)", key), format!("pkh({}/1/*)", key)),
+        ]
+    );
+
+    let decoded = decode_ur(&ur, Network::Testnet)?;
+    assert_eq!(
+        decoded.core,
+        serde_json::json!([format!("wpkh({})", key), format!("pkh({}/0/*)", key)])
+    );
+
+    // there is no telling the change descriptor of any other children
+    for children in &["/*", "/2/*", "/1/*"] {
+        let wpkh = Value::Tag(404, Box::new(key_to_cbor(&format!("{}{}", key, children))?));
+        let mut account = BTreeMap::new();
+        account.insert(Value::Integer(1), Value::Integer(0xc258d2e4));
+        account.insert(
+            Value::Integer(2),
+            Value::Array(vec![Value::Tag(308, Box::new(wpkh))]),
+        );
+        let ur = cbor_as_ur("crypto-account", &Value::Map(account))?;
+        match parse_ur_account_descriptors(ur, Network::Testnet) {
+            Err(SweepError::Ur(e)) => {
+                assert!(e.contains(&format!("wpkh({}{})", key, children)))
+            }
+            other => panic!("unexpected result for {}: {:?}", children, other),
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn private_hdkey_test() -> Result<(), SweepError> {
+    let inp = "[df4cd1b2/48h/1h/0h/2h]tprv8i2Zqn1v72sJunpNRwQaEFBNGQ8extLZauN3DY6RAkCa62GX59h3CHrvNhL7ycehLQ7Q3MqQqp42wgDwJL8Nxjgvz71gWDA9Uwb7QQMYqza";
+
+    let ur = hdkey_as_ur(inp)?;
+    assert!(is_ur_signing_key(&ur));
+
+    let decoded = decode_ur(&ur, Network::Testnet)?;
+    assert_eq!(decoded.core, serde_json::json!(inp));
+
+    let (xprv, origin) = parse_ur_signing_key(&ur, Network::Testnet)?;
+    assert_eq!(
+        xprv,
+        ExtendedPrivKey::from_str(inp.split_once(']').unwrap().1).unwrap()
+    );
+    let (fingerprint, path) = origin.unwrap();
+    assert_eq!(fingerprint.to_string(), "df4cd1b2");
+    assert_eq!(path, DerivationPath::from_str("m/48'/1'/0'/2'").unwrap());
+
+    // a descriptor with a private key survives the round trip as well
+    let desc = format!("wpkh({}/0/*)", inp);
+    assert_eq!(
+        parse_ur_descriptor(descriptor_as_ur(&desc)?, Network::Testnet)?,
+        desc
+    );
+
+    // public keys can't sign
+    let ur = hdkey_as_ur("[c258d2e4/84h/1h/0h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE")?;
+    assert!(parse_ur_signing_key(&ur, Network::Testnet).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn malformed_ur_test() -> Result<(), SweepError> {
+    let ur = "ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadremh";
+    assert!(parse_ur_descriptor(ur.to_string(), Network::Bitcoin).is_ok());
+
+    // a misread word breaks the checksum
+    let typo = ur.replacen("tstyng", "tstynd", 1);
+    assert!(parse_ur_descriptor(typo, Network::Bitcoin).is_err());
+    let not_a_word = ur.replacen("tstyng", "tstyqq", 1);
+    assert!(parse_ur_descriptor(not_a_word, Network::Bitcoin).is_err());
+
+    // scheme, casing and type
+    let mixed_case = ur.replacen("ur:crypto-output", "UR:crypto-output", 1);
+    assert!(parse_ur_descriptor(mixed_case, Network::Bitcoin).is_err());
+    let scheme = ur.replacen("ur:", "xr:", 1);
+    assert!(decode_ur(&scheme, Network::Bitcoin).is_err());
+    assert!(decode_ur_address(ur.to_string()).is_err());
+    assert!(decode_ur("ur:crypto-output", Network::Bitcoin).is_err());
+    let multi_part = ur.replacen("crypto-output/", "crypto-output/1-3/", 1);
+    assert!(decode_ur(&multi_part, Network::Bitcoin).is_err());
+
+    // the checksum is fine, but the CBOR is cut short
+    let (_key, payload) = ur.split_once('/').unwrap();
+    let cbor = bytewords::decode(payload, &bytewords::Style::Minimal)?;
+    let truncated = format!(
+        "ur:crypto-output/{}",
+        bytewords::encode(&cbor[..cbor.len() - 5], &bytewords::Style::Minimal)
+    );
+    assert!(parse_ur_descriptor(truncated, Network::Bitcoin).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn malformed_eckey_test() -> Result<(), SweepError> {
+    let key =
+        hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+    let eckey = |entries: Vec<(i128, Value)>| {
+        let map: BTreeMap<Value, Value> = entries
+            .into_iter()
+            .map(|(k, v)| (Value::Integer(k), v))
+            .collect();
+        cbor_as_ur("crypto-eckey", &Value::Map(map))
+    };
+    let ur_error = |ur: String, message: &str| match decode_ur(&ur, Network::Testnet) {
+        Err(SweepError::Ur(e)) => assert!(e.contains(message), "{}", e),
+        other => panic!("expected a ur error with {}, got {:?}", message, other),
+    };
+
+    let ur = eckey(vec![(1, Value::Integer(0)), (3, Value::Bytes(key.clone()))])?;
+    assert_eq!(
+        decode_ur(&ur, Network::Testnet)?.core,
+        serde_json::json!(hex::encode(&key))
+    );
+
+    ur_error(
+        cbor_as_ur("crypto-eckey", &Value::Bytes(key.clone()))?,
+        "crypto-eckey: expected a map",
+    );
+    ur_error(
+        eckey(vec![(2, Value::Bool(false))])?,
+        "crypto-eckey: missing data",
+    );
+    ur_error(
+        eckey(vec![(1, Value::Integer(1)), (3, Value::Bytes(key))])?,
+        "crypto-eckey: unsupported curve",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn outputdesc_nesting_test() -> Result<(), SweepError> {
+    let inp = hex::decode("d90190d90196a201020282d90132a1035821022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01d90132a103582103acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe").unwrap();
+    let data: Value = serde_cbor::from_slice(&inp).unwrap();
+    let multi = match data {
+        Value::Tag(400, ref multi) => *multi.clone(),
+        _ => panic!("test vector is sh(multi())"),
+    };
+    let key = match multi {
+        Value::Tag(406, ref m) => match **m {
+            Value::Map(ref m) => match m.get(&Value::Integer(2)) {
+                Some(Value::Array(keys)) => keys[0].clone(),
+                _ => panic!("test vector has keys"),
+            },
+            _ => panic!("test vector has a map"),
+        },
+        _ => panic!("test vector is sh(multi())"),
+    };
+
+    let parse = |val: Value| parse_ur_desc(val, Network::Bitcoin, &mut String::new());
+
+    // wsh(sh())
+    assert!(parse(Value::Tag(401, Box::new(data.clone()))).is_err());
+    // wpkh(multi())
+    assert!(parse(Value::Tag(404, Box::new(multi.clone()))).is_err());
+    // sh(wpkh()) with an unknown key type
+    let unknown_key = Value::Tag(404, Box::new(Value::Tag(305, Box::new(Value::Null))));
+    assert!(parse(Value::Tag(400, Box::new(unknown_key))).is_err());
+    // a bare key is not a script
+    assert!(parse(key.clone()).is_err());
+    assert!(parse(Value::Tag(402, Box::new(key.clone()))).is_ok());
+
+    // 3 of 2
+    let mut threshold = BTreeMap::new();
+    threshold.insert(Value::Integer(1), Value::Integer(3));
+    threshold.insert(Value::Integer(2), Value::Array(vec![key.clone(), key]));
+    let mut out = String::new();
+    let result = parse_ur_desc(
+        Value::Tag(
+            401,
+            Box::new(Value::Tag(406, Box::new(Value::Map(threshold)))),
+        ),
+        Network::Bitcoin,
+        &mut out,
+    );
+    assert!(result.is_err());
+    assert_eq!(out, "wsh(multi(");
+
+    Ok(())
+}
+
+#[test]
+fn keypath_components_test() {
+    let keypath = |components: Vec<Value>| {
+        let mut m = BTreeMap::new();
+        m.insert(Value::Integer(1), Value::Array(components));
+        m.insert(Value::Integer(2), Value::Integer(0xc258d2e4));
+        serde_cbor::to_vec(&Value::Tag(304, Box::new(Value::Map(m)))).unwrap()
+    };
+
+    let path: CryptoKeyPath =
+        serde_cbor::from_slice(&keypath(vec![Value::Integer(84), Value::Bool(true)])).unwrap();
+    assert_eq!(path.components_str, "");
+    assert_eq!(path.depth, 1);
+
+    // no components
+    assert!(serde_cbor::from_slice::<CryptoKeyPath>(&keypath(vec![])).is_ok());
+    assert!(serde_cbor::from_slice::<CryptoKeyPath2>(&keypath(vec![])).is_ok());
+    // missing hardened flag
+    let odd = keypath(vec![Value::Integer(84)]);
+    assert!(serde_cbor::from_slice::<CryptoKeyPath>(&odd).is_err());
+    assert!(serde_cbor::from_slice::<CryptoKeyPath2>(&odd).is_err());
+    // child number out of range
+    let big = keypath(vec![Value::Integer(0x8000_0000), Value::Bool(false)]);
+    assert!(serde_cbor::from_slice::<CryptoKeyPath>(&big).is_err());
+    assert!(serde_cbor::from_slice::<CryptoKeyPath2>(&big).is_err());
+    // wrong tag
+    let wrong_tag = serde_cbor::to_vec(&Value::Tag(305, Box::new(Value::Null))).unwrap();
+    assert!(serde_cbor::from_slice::<CryptoKeyPath>(&wrong_tag).is_err());
+}
+
 #[test]
 fn hdkey_test_vector_1() -> Result<(), SweepError> {
     let mut inp =
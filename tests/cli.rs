@@ -4,6 +4,7 @@ use bdk::bitcoin::util::psbt::*;
 use bdk::bitcoin::Address;
 use bdk::bitcoin::Network;
 use bdk::blockchain::noop_progress;
+use bdk::blockchain::Blockchain;
 use bdk::blockchain::ElectrumBlockchain;
 use bdk::database::MemoryDatabase;
 use bdk::electrum_client::Client;
@@ -11,11 +12,16 @@ use bdk::Wallet;
 use predicates::prelude::*; // Used for writing assertions
 use serde_json::Value;
 use std::process::Command;
+use ur_rs::bytewords;
 
 const SWEEPTOOL: &str = "sweeptool";
 const NIGIRI: &str = "nigiri";
 const CLIENT_URL: &str = "127.0.0.1:51401";
 
+/// Descriptors of the tests failing before any UTXO is swept
+const DESCRIPTOR: &str = "pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/0/*)";
+const DESCRIPTOR_CHG: &str = "pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/1/*)";
+
 #[test]
 fn help_subcommand() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
@@ -29,6 +35,680 @@ fn help_subcommand() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn ur_encode_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    // source: https://github.com/BlockchainCommons/Research/blob/master/papers/bcr-2020-010-output-desc.md#exampletest-vector-3
+    let desc = "sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))";
+
+    cmd.arg("ur").arg("encode").arg(desc);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""ur":"ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadremh""#));
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("ur")
+        .arg("encode")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26");
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("ur:crypto-address/"));
+
+    Ok(())
+}
+
+#[test]
+fn ur_decode_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    let ur = "ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadremh";
+
+    cmd.arg("ur").arg("decode").arg(ur);
+
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(r#""type":"crypto-output""#))
+        .stdout(predicate::str::contains(r#""core":"sh(multi(2,022f01e5e15cca351daff3843fb70f3c2f0a1bdd05e5af888a67784ef3e10a2a01,03acd484e2f0c7f65309ad178a9f559abde09796974c57e714c35f110dfc27ccbe))""#));
+
+    // a misread UR is rejected with the ur error code instead of a panic
+    let payload = ur.split_once('/').unwrap().1;
+    for malformed in &[
+        // mixed case
+        format!("UR:crypto-output/{}", payload),
+        format!("xr:crypto-output/{}", payload),
+        format!("ur:crypto-output/1-2/{}", payload),
+        // truncated
+        format!("ur:crypto-output/{}", &payload[..payload.len() - 2]),
+        "ur:crypto-output".to_string(),
+    ] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("ur").arg("decode").arg(malformed);
+        cmd.assert()
+            .failure()
+            .code(4)
+            .stderr(predicate::str::contains("ur error (32)"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn json_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    // the checksum of the UR is broken
+    let ur = "ur:crypto-output/taadmhtaadmtoeadaoaolftaadeyoyaxhdclaodladvwvyhhsgeccapewflrfhrlbsfndlbkcwutahvwpeloleioksglwfvybkdradtaadeyoyaxhdclaxpstylrvowtstynguaspmchlenegonyryvtmsmtmsgshgvdbbsrhebybtztdisfrnpfadrehd";
+
+    cmd.arg("ur").arg("decode").arg(ur).arg("--json-errors");
+
+    cmd.assert()
+        .failure()
+        .code(4)
+        .stderr(predicate::str::contains(r#""code":32"#))
+        .stderr(predicate::str::contains(r#""kind":"ur""#));
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    // the change descriptor is missing
+    cmd.arg("--json-errors")
+        .arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-a")
+        .arg("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv");
+
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains(r#""kind":"cli""#));
+
+    Ok(())
+}
+
+#[test]
+fn privacy_requires_dest_descriptor() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    // every PSBT would pay to the same address
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")
+        .arg("--privacy")
+        .arg("utxo");
+
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("dest-descriptor"));
+
+    Ok(())
+}
+
+#[test]
+fn policy_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    // spent by both keys, or after block 800000 by the first key alone
+    let desc = "wsh(thresh(2,pk([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*),s:pk([8a6d2a0c/84h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/0/*),sln:after(800000)))";
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("policy").arg("-d").arg(desc);
+
+    let out = cmd.output().unwrap();
+    assert!(out.status.success());
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    assert_eq!(val["external"]["type"], "THRESH");
+    assert_eq!(val["external"]["items"].as_array().unwrap().len(), 3);
+    assert!(val["external"]["id"].is_string());
+    assert!(val["internal"].is_null());
+
+    // the policy path is validated before connecting to a server
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(desc)
+        .arg("-c")
+        .arg(desc)
+        .arg("-a")
+        .arg("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")
+        .arg("--policy-path")
+        .arg("recovery");
+
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("invalid policy path"));
+
+    Ok(())
+}
+
+#[test]
+fn bump_fee_and_cpfp_arguments() -> Result<(), Box<dyn std::error::Error>> {
+    // neither the PSBT nor the txid of the sweep
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("bump-fee")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG);
+    cmd.assert().failure().code(2);
+
+    // the destination descriptors go together
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("bump-fee")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-e")
+        .arg(DESCRIPTOR)
+        .arg("--txid")
+        .arg("6526d8d7fd8bb50ea9b8ece41088f41048731ea7210fe603cd335a1d9a540f2b");
+    cmd.assert().failure().code(2);
+
+    // checked before connecting to a server
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("bump-fee")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("--txid")
+        .arg("not-a-txid");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("invalid txid"));
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("cpfp")
+        .arg("-e")
+        .arg(DESCRIPTOR)
+        .arg("-s")
+        .arg(DESCRIPTOR_CHG)
+        .arg("--txid")
+        .arg("not-a-txid");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("invalid txid"));
+
+    Ok(())
+}
+
+#[test]
+fn backend_arguments() -> Result<(), Box<dyn std::error::Error>> {
+    // the user and the password of a Bitcoin Core node come in pairs
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--rpc")
+        .arg("http://127.0.0.1:18443")
+        .arg("--rpc-password")
+        .arg("123");
+    cmd.assert().failure().code(2);
+
+    // a quorum of more servers than given
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--server")
+        .arg("ssl://electrum.blockstream.info:60002")
+        .arg("--quorum")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("quorum 2 out of 1 servers"));
+
+    // compact block filters are downloaded from a peer only
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--skip-blocks")
+        .arg("100");
+    cmd.assert().failure().code(2);
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--peer")
+        .arg("127.0.0.1:18444")
+        .arg("--rpc")
+        .arg("http://127.0.0.1:18443");
+    cmd.assert().failure().code(2);
+
+    Ok(())
+}
+
+#[test]
+fn config_file_and_timeout() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    // a typo in the config file
+    let mut config = tempfile::NamedTempFile::new()?;
+    writeln!(config, "timeout = 5\nretries = 3")?;
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.env("SWEEPTOOL_CONFIG", config.path())
+        .arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("invalid config file"));
+
+    // a proxy accepting the connection and never answering, the timeout of the config file
+    // ends the sync
+    let proxy = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let mut config = tempfile::NamedTempFile::new()?;
+    writeln!(config, "timeout = 2")?;
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.env("SWEEPTOOL_CONFIG", config.path())
+        .arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--server")
+        .arg("tcp://electrum.example.onion:50001")
+        .arg("--proxy")
+        .arg(proxy.local_addr()?.to_string());
+    cmd.assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("within the timeout of 2 s"));
+
+    Ok(())
+}
+
+#[test]
+fn profiles_and_environment() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+
+    // a proxy accepting the connection and never answering
+    let proxy = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let mut config = tempfile::NamedTempFile::new()?;
+    writeln!(
+        config,
+        "timeout = 300\n\
+         [profile.testnet-tor]\n\
+         server = [\"tcp://electrum.example.onion:50001\"]\n\
+         proxy = \"{}\"\n\
+         [profile.signet]\n\
+         network = \"signet\"",
+        proxy.local_addr()?
+    )?;
+
+    let sweep = |profile: &str| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.env("SWEEPTOOL_CONFIG", config.path())
+            .arg("sweep")
+            .arg("-d")
+            .arg(DESCRIPTOR)
+            .arg("-c")
+            .arg(DESCRIPTOR_CHG)
+            .arg("-a")
+            .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+            .arg("--profile")
+            .arg(profile);
+        Ok(cmd)
+    };
+
+    // the server and the proxy of the profile, the timeout of the environment
+    sweep("testnet-tor")?
+        .env("SWEEPTOOL_TIMEOUT", "2")
+        .assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("within the timeout of 2 s"));
+
+    // the RPC server can't be reached through the proxy of the profile
+    sweep("testnet-tor")?
+        .arg("--rpc")
+        .arg("http://127.0.0.1:18332")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("configured proxy"));
+
+    sweep("mainnet-tor")?
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("no profile mainnet-tor"));
+
+    sweep("signet")?
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("invalid network signet"));
+
+    sweep("testnet-tor")?
+        .env("SWEEPTOOL_TIMEOUT", "soon")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("SWEEPTOOL_TIMEOUT"));
+
+    Ok(())
+}
+
+#[test]
+fn esplora_proxy() -> Result<(), Box<dyn std::error::Error>> {
+    // the credentials are user:password
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--esplora")
+        .arg("http://esplora.example.onion/testnet/api")
+        .arg("--proxy")
+        .arg("127.0.0.1:9050")
+        .arg("--proxy-auth")
+        .arg("sweep");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("user:password"));
+
+    // the onion address isn't resolved locally but sent to the proxy, which accepts the
+    // credentials and then never answers the request to connect
+    let proxy = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let (handshake, received) = std::sync::mpsc::channel();
+    let listener = proxy.try_clone()?;
+    std::thread::spawn(move || -> std::io::Result<()> {
+        use std::io::{Read, Write};
+        let (mut stream, _) = listener.accept()?;
+        let read = |stream: &mut std::net::TcpStream| -> std::io::Result<Vec<u8>> {
+            let mut buf = [0u8; 512];
+            let len = stream.read(&mut buf)?;
+            Ok(buf[..len].to_vec())
+        };
+        let greeting = read(&mut stream)?;
+        // username/password authentication
+        stream.write_all(&[5, 2])?;
+        let auth = read(&mut stream)?;
+        stream.write_all(&[1, 0])?;
+        let connect = read(&mut stream)?;
+        handshake.send((greeting, auth, connect)).unwrap();
+        // the connection stays open until the command times out
+        std::thread::sleep(std::time::Duration::from_secs(10));
+        Ok(())
+    });
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(DESCRIPTOR)
+        .arg("-c")
+        .arg(DESCRIPTOR_CHG)
+        .arg("-a")
+        .arg("2NA2wt6vsNpENreZEydjevbuvg81v6Mej26")
+        .arg("--esplora")
+        .arg("http://esplora.example.onion/testnet/api")
+        .arg("--proxy")
+        .arg(proxy.local_addr()?.to_string())
+        .arg("--proxy-auth")
+        .arg("sweep:1")
+        .arg("--timeout")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .code(3)
+        .stderr(predicate::str::contains("within the timeout of 2 s"));
+
+    let (greeting, auth, connect) = received.recv_timeout(std::time::Duration::from_secs(1))?;
+    // SOCKS5 offering username/password authentication
+    assert_eq!(greeting[0], 5);
+    assert!(greeting[2..].contains(&2));
+    // version 1, user "sweep", password "1"
+    assert_eq!(auth, b"\x01\x05sweep\x011".to_vec());
+    // CONNECT to the domain name, port 80
+    let host = b"esplora.example.onion";
+    assert_eq!(connect[..5], [5, 1, 0, 3, host.len() as u8]);
+    assert_eq!(&connect[5..5 + host.len()], host);
+    assert_eq!(connect[5 + host.len()..], [0, 80]);
+
+    Ok(())
+}
+
+#[test]
+fn offline_sweep() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::str::FromStr;
+
+    let e = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/0/*)";
+    let s = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/1/*)";
+    let addr = "2NA2wt6vsNpENreZEydjevbuvg81v6Mej26";
+
+    // a segwit UTXO at the first address of e, and one of another wallet
+    let script =
+        |address: &str| format!("{:x}", Address::from_str(address).unwrap().script_pubkey());
+    let utxos = serde_json::json!({
+        "network": "regtest",
+        "height": 200,
+        "utxos": [
+            {
+                "outpoint": "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:0",
+                "witness_utxo": {
+                    "value": 100_000,
+                    "script_pubkey": script("bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs")
+                }
+            },
+            {
+                "outpoint": "77bf380f128e6b4e1072b2d2387ffd761bb1174777a2a8dc0d6ea4b394421040:1",
+                "witness_utxo": {
+                    "value": 200_000,
+                    "script_pubkey": script("mn9qXHZsAQT6A1fkMvi5nmWmCzUEyLWZhv")
+                }
+            }
+        ]
+    });
+    let mut file = tempfile::NamedTempFile::new()?;
+    write!(file, "{}", utxos)?;
+
+    // the fee rate can't be estimated offline
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(e)
+        .arg("-c")
+        .arg(s)
+        .arg("-a")
+        .arg(addr)
+        .arg("-n")
+        .arg("regtest")
+        .arg("--utxos-file")
+        .arg(file.path());
+    cmd.assert().failure().code(2);
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(e)
+        .arg("-c")
+        .arg(s)
+        .arg("-a")
+        .arg(addr)
+        .arg("-n")
+        .arg("regtest")
+        .arg("--utxos-file")
+        .arg(file.path())
+        .arg("--fee-rate")
+        .arg("2");
+    let out = cmd.output().unwrap();
+    assert!(out.status.success());
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    assert_eq!(val["locktime"], 200);
+
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(val["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    // the UTXO of the other wallet is left out
+    assert_eq!(psbt.inputs.len(), 1);
+    assert_eq!(psbt.inputs[0].witness_utxo.as_ref().unwrap().value, 100_000);
+    assert_eq!(
+        psbt.global.unsigned_tx.output[0].value + val["fees"].as_u64().unwrap(),
+        100_000
+    );
+
+    // the summary shows where the funds go and what the fee is, only yes confirms it
+    for (answer, code) in &[("no\n", 8), ("yes\n", 0)] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("sweep")
+            .arg("-d")
+            .arg(e)
+            .arg("-c")
+            .arg(s)
+            .arg("-a")
+            .arg(addr)
+            .arg("-n")
+            .arg("regtest")
+            .arg("--utxos-file")
+            .arg(file.path())
+            .arg("--fee-rate")
+            .arg("2")
+            .arg("--interactive");
+        let assert = cmd
+            .with_stdin()
+            .buffer(*answer)
+            .assert()
+            .code(*code)
+            .stderr(predicate::str::contains(addr))
+            .stderr(predicate::str::contains("0.00100000 BTC"))
+            .stderr(predicate::str::contains("% of the funds"))
+            .stderr(predicate::str::contains("Type yes to confirm"));
+        if *code == 0 {
+            assert.stdout(predicate::str::contains("psbt"));
+        } else {
+            assert.stdout(predicate::str::is_empty());
+        }
+    }
+
+    // inspecting the PSBT tells the input derived from e, in base64 and in UR format
+    for psbt in &[val["psbt"]["base64"].as_str(), val["psbt"]["ur"].as_str()] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("inspect")
+            .arg(psbt.unwrap())
+            .arg("-d")
+            .arg(e)
+            .arg("-d")
+            .arg(s)
+            .arg("-n")
+            .arg("regtest");
+        let out = cmd.output().unwrap();
+        assert!(out.status.success());
+        let inspected: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+        assert_eq!(inspected["fee"], val["fees"]);
+        assert_eq!(inspected["complete"], false);
+        assert_eq!(inspected["inputs"][0]["status"], "unsigned");
+        assert_eq!(inspected["inputs"][0]["derived_from"]["descriptor"], e);
+        assert_eq!(inspected["inputs"][0]["derived_from"]["index"], 0);
+        assert_eq!(inspected["outputs"][0]["address"], addr);
+        assert!(inspected["outputs"][0].get("derived_from").is_none());
+    }
+
+    // sign refuses another destination or a higher fee than expected, before signing
+    let fees = val["fees"].as_u64().unwrap();
+    let other = "bcrt1qzg4mckdh50nwdm9hkzq06528rsu73hjxytqkxs";
+    for (args, code) in &[
+        (
+            vec![
+                "--expect-dest",
+                addr,
+                "--max-fee",
+                fees.to_string().as_str(),
+            ],
+            0,
+        ),
+        (vec!["--expect-dest", other], 7),
+        (vec!["--expect-dest", e, "--expect-dest", s], 7),
+        (vec!["--max-fee", (fees - 1).to_string().as_str()], 7),
+        (vec!["--max-fee-rate", "1.5"], 7),
+    ] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("sign")
+            .arg("-d")
+            .arg(e)
+            .arg(val["psbt"]["base64"].as_str().unwrap())
+            .arg("-n")
+            .arg("regtest")
+            .args(args);
+        cmd.assert().code(*code);
+    }
+
+    // the review shows regtest addresses, like inspect does
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sign")
+        .arg("-d")
+        .arg(e)
+        .arg(val["psbt"]["base64"].as_str().unwrap())
+        .arg("-n")
+        .arg("regtest")
+        .arg("--interactive");
+    cmd.with_stdin()
+        .buffer("no\n")
+        .assert()
+        .code(8)
+        .stderr(predicate::str::contains(other));
+
+    // a file of another network
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(e)
+        .arg("-c")
+        .arg(s)
+        .arg("-a")
+        .arg("tb1qvctwrh8ckrex8daxya4xleaevcp299ttd9gnev")
+        .arg("--utxos-file")
+        .arg(file.path())
+        .arg("--fee-rate")
+        .arg("2");
+    cmd.assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("the UTXOs file is for regtest"));
+
+    Ok(())
+}
+
 // run with: cargo test --features nigiri
 #[test]
 #[cfg_attr(not(feature = "nigiri"), ignore)]
@@ -168,6 +848,15 @@ fn test_sweeping() -> Result<(), Box<dyn std::error::Error>> {
     let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
 
     if let Value::Object(m) = val {
+        // Every input is paired with the destination address of the same keychain and index
+        let mapping = m.get("mapping").unwrap().as_array().unwrap();
+        assert_eq!(mapping.len(), 4);
+        for pair in mapping {
+            assert_eq!(pair["source"]["keychain"], pair["destination"]["keychain"]);
+            assert_eq!(pair["source"]["index"], pair["destination"]["index"]);
+            assert!(pair["value_out"].as_u64().unwrap() < pair["value_in"].as_u64().unwrap());
+        }
+
         let psbt = m.get("psbt").unwrap();
         if let Value::Object(m) = psbt {
             let psbt = m.get("base64").unwrap();
@@ -284,6 +973,41 @@ fn test_sweeping() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // TEST CASE: with an address gap limit of 2 the sync finds Rx address 3 past the unused
+    // address 2, and Chg address 2. Their destinations have the same index, beyond the gap
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-e")
+        .arg(e)
+        .arg("-s")
+        .arg(s)
+        .arg("-n")
+        .arg("regtest")
+        .arg("-g")
+        .arg("2");
+
+    let out = cmd.output().unwrap();
+    assert!(out.status.success());
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    let mapping = val["mapping"].as_array().unwrap();
+    assert_eq!(mapping.len(), 4);
+    let past_gap: Vec<&Value> = mapping
+        .iter()
+        .filter(|pair| pair["source"]["index"].as_u64().unwrap() >= 2)
+        .collect();
+    assert_eq!(past_gap.len(), 2);
+    for pair in past_gap {
+        assert_eq!(pair["source"]["index"], pair["destination"]["index"]);
+    }
+    assert!(mapping.iter().any(
+        |pair| pair["destination"]["address"] == "bcrt1qkn7qfsmdxgktpnquhq8q6nmvastac2dw2cxmrd"
+    ));
+
     // TEST CASE: let's sweep the funds to some other destination (Rcv) output descriptor in UR format
     let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
 
@@ -378,6 +1102,80 @@ fn test_sweeping() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // TEST CASE: sweep every UTXO with a PSBT of its own
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-e")
+        .arg(e)
+        .arg("-s")
+        .arg(s)
+        .arg("--privacy")
+        .arg("utxo")
+        .arg("--fee-jitter")
+        .arg("20")
+        .arg("-n")
+        .arg("regtest");
+
+    let out = cmd.output().unwrap();
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    let outs = val.as_array().unwrap();
+    assert_eq!(outs.len(), 4);
+
+    for out in outs {
+        let psbt = out["psbt"]["base64"].as_str().unwrap();
+        let psbt: PartiallySignedTransaction =
+            bdk::bitcoin::consensus::deserialize(&base64::decode(psbt).unwrap()).unwrap();
+        let tx = psbt.extract_tx();
+
+        // one input, one output to the address with the keychain and index of the input
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.output.len(), 1);
+        let mapping = out["mapping"].as_array().unwrap();
+        assert_eq!(mapping.len(), 1);
+        assert_eq!(
+            mapping[0]["outpoint"],
+            tx.input[0].previous_output.to_string()
+        );
+        assert_eq!(
+            mapping[0]["source"]["index"],
+            mapping[0]["destination"]["index"]
+        );
+        assert_eq!(
+            mapping[0]["destination"]["address"],
+            Address::from_script(&tx.output[0].script_pubkey, Network::Regtest)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    // the inputs are p2pkh and the outputs p2wpkh
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-e")
+        .arg(e)
+        .arg("-s")
+        .arg(s)
+        .arg("--privacy")
+        .arg("address")
+        .arg("--check-script-type")
+        .arg("-n")
+        .arg("regtest");
+
+    cmd.assert()
+        .failure()
+        .code(4)
+        .stderr(predicate::str::contains("script_type error (34)"));
+
     // TEST CASE: sweep to an address
     let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
 
@@ -431,6 +1229,35 @@ fn test_sweeping() -> Result<(), Box<dyn std::error::Error>> {
                     r#""fees":"#, fees
                 )));
 
+            // TEST CASE: sign a PSBT with the private key as a crypto-hdkey. The script type and the
+            // derivation path are taken from the PSBT
+            let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+            cmd.arg("ur")
+                .arg("encode")
+                .arg("[c258d2e4/44h/1h/0h]tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV");
+            let out = cmd.output().unwrap();
+            let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+            let hdkey = val["ur"].as_str().unwrap().to_string();
+
+            let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+            cmd.arg("sign").arg("-d").arg(&hdkey).arg(psbt_str);
+
+            let out = cmd.output().unwrap();
+            let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+            let signed: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+                &base64::decode(val["base64"].as_str().unwrap()).unwrap(),
+            )
+            .unwrap();
+            let raw_tx = hex::encode(serialize(&signed.extract_tx()));
+
+            let mut nigiri = Command::new(NIGIRI);
+            nigiri
+                .arg("rpc")
+                .arg("testmempoolaccept")
+                .arg(format!(r#"["{}"]"#, raw_tx));
+            let out = nigiri.output().unwrap();
+            assert!(String::from_utf8_lossy(&out.stdout).contains("\"allowed\": true"));
+
             // TEST CASE: sign a PSBT and verify if the signed PSBT is valid
             let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
             // xpriv: tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV
@@ -482,5 +1309,358 @@ fn test_sweeping() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    // TEST CASE: sweep a crypto-account, as exported by a hardware wallet, holding d and an
+    // unused descriptor. c is derived from d, the unused descriptor gets no PSBT
+    let unused_account = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/7/*)";
+    let mut outputs = Vec::new();
+    for desc in &[d, unused_account] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("ur").arg("encode").arg(desc);
+        let out = cmd.output().unwrap();
+        let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+        let payload = val["ur"].as_str().unwrap().split_once('/').unwrap().1;
+        let cbor = bytewords::decode(payload, &bytewords::Style::Minimal).unwrap();
+        let output: serde_cbor::Value = serde_cbor::from_slice(&cbor)?;
+        outputs.push(serde_cbor::Value::Tag(308, Box::new(output)));
+    }
+    let mut account = std::collections::BTreeMap::new();
+    account.insert(
+        serde_cbor::Value::Integer(1),
+        serde_cbor::Value::Integer(0xc258d2e4),
+    );
+    account.insert(
+        serde_cbor::Value::Integer(2),
+        serde_cbor::Value::Array(outputs),
+    );
+    let account = format!(
+        "ur:crypto-account/{}",
+        bytewords::encode(
+            &serde_cbor::to_vec(&serde_cbor::Value::Map(account))?,
+            &bytewords::Style::Minimal
+        )
+    );
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(&account)
+        .arg("-a")
+        .arg(addr)
+        .arg("-n")
+        .arg("regtest");
+
+    let out = cmd.output().unwrap();
+    assert!(out.status.success());
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    let outs = val.as_array().unwrap();
+    assert_eq!(outs.len(), 1);
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(outs[0]["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    // the UTXOs of both keychains of d
+    assert_eq!(
+        psbt.global.unsigned_tx.input.len(),
+        wallet_origin.list_unspent()?.len()
+    );
+    assert_eq!(outs[0]["address"][0], addr);
+
+    // TEST CASE: a sweep that can't be mined before a later block height
+    let locktime = wallet_origin.client().get_height()? + 100;
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-a")
+        .arg(addr)
+        .arg("--locktime")
+        .arg(locktime.to_string())
+        .arg("-n")
+        .arg("regtest");
+
+    let out = cmd.output().unwrap();
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    assert_eq!(val["locktime"], locktime);
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(val["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(psbt.global.unsigned_tx.lock_time, locktime);
+
+    // TEST CASE: the same sweep through the Bitcoin Core node of nigiri instead of Electrum
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-a")
+        .arg(addr)
+        .arg("--rpc")
+        .arg("http://127.0.0.1:18443")
+        .arg("--rpc-user")
+        .arg("admin1")
+        .arg("--rpc-password")
+        .arg("123")
+        .arg("-n")
+        .arg("regtest");
+
+    let out = cmd.output().unwrap();
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(val["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    // scantxoutset finds the UTXOs the Electrum server does
+    assert_eq!(
+        psbt.global.unsigned_tx.input.len(),
+        wallet_origin.list_unspent()?.len()
+    );
+    assert_eq!(val["address"][0], addr);
+
+    // TEST CASE: the same sweep synced from the compact block filters of the node of nigiri,
+    // which serves them (blockfilterindex=1, peerblockfilters=1). Peers estimate no fees, only
+    // regtest falls back on the minimum relay fee
+    let cbf_dir = tempfile::tempdir()?;
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-a")
+        .arg(addr)
+        .arg("--peer")
+        .arg("127.0.0.1:18444")
+        .arg("--cbf-dir")
+        .arg(cbf_dir.path())
+        .arg("-n")
+        .arg("regtest");
+
+    let out = cmd.output().unwrap();
+    assert!(out.status.success());
+    let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(val["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    // the filters match the blocks of the UTXOs the Electrum server finds
+    assert_eq!(
+        psbt.global.unsigned_tx.input.len(),
+        wallet_origin.list_unspent()?.len()
+    );
+    assert_eq!(val["address"][0], addr);
+
+    // TEST CASE: the same sweep with a server failing over to the next, and with two
+    // servers having to agree on the UTXOs
+    for servers in &[
+        vec!["127.0.0.1:1", CLIENT_URL],
+        vec![CLIENT_URL, CLIENT_URL],
+    ] {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("sweep")
+            .arg("-d")
+            .arg(d)
+            .arg("-c")
+            .arg(c)
+            .arg("-a")
+            .arg(addr)
+            .arg("-n")
+            .arg("regtest");
+        for server in servers {
+            cmd.arg("--server").arg(server);
+        }
+        if servers[0] == CLIENT_URL {
+            cmd.arg("--quorum").arg("2");
+        }
+
+        let out = cmd.output().unwrap();
+        let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+        let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+            &base64::decode(val["psbt"]["base64"].as_str().unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            psbt.global.unsigned_tx.input.len(),
+            wallet_origin.list_unspent()?.len()
+        );
+    }
+
+    // TEST CASE: bump the fee of a broadcast sweep to a descriptor
+    let c_prv="pkh([c258d2e4/44h/1h/0h]tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV/1/*)";
+    let d_prv="pkh([c258d2e4/44h/1h/0h]tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV/0/*)";
+    // destination descriptors of the same key, to sign a child of the sweep
+    let e_own="pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/2/*)";
+    let s_own="pkh([c258d2e4/44h/1h/0h]tpubD6NzVbkrYhZ4Yg9Rz1bXTTrc4TqZ8odbPaXrnrWX6cbDsXvH96FLDeRsckXohEkzGdAn5hbtK6iN7pCB1DeUpVwofEXCsN2StwWtU2SxE3f/3/*)";
+    let e_prv="pkh([c258d2e4/44h/1h/0h]tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV/2/*)";
+    let s_prv="pkh([c258d2e4/44h/1h/0h]tprv8ZgxMBicQKsPfD7e6Mvw44CVVSKcyUSgpGw5WLUDgLnq33fWWhRk39p1ScW8Potbk7PRf3vv6XWN5CpwcY1Xwke6aegWg6y82Agt9FnxCFV/3/*)";
+    let sign_tx = |psbt: &str,
+                   descriptor: &str,
+                   descriptor_chg: &str|
+     -> Result<String, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+        cmd.arg("sign")
+            .arg("-d")
+            .arg(descriptor)
+            .arg("-c")
+            .arg(descriptor_chg)
+            .arg(psbt);
+        let out = cmd.output().unwrap();
+        let val: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+        let signed: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+            &base64::decode(val["base64"].as_str().unwrap()).unwrap(),
+        )
+        .unwrap();
+        Ok(hex::encode(serialize(&signed.extract_tx())))
+    };
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-e")
+        .arg(e_own)
+        .arg("-s")
+        .arg(s_own)
+        .arg("-n")
+        .arg("regtest");
+    let out = cmd.output().unwrap();
+    let original: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+
+    let raw_tx = sign_tx(original["psbt"]["base64"].as_str().unwrap(), d_prv, c_prv)?;
+    let mut nigiri = Command::new(NIGIRI);
+    nigiri.arg("rpc").arg("sendrawtransaction").arg(raw_tx);
+    assert!(nigiri.output().unwrap().status.success());
+
+    thread::sleep(Duration::from_millis(1000));
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("bump-fee")
+        .arg("-d")
+        .arg(d)
+        .arg("-c")
+        .arg(c)
+        .arg("-e")
+        .arg(e_own)
+        .arg("-s")
+        .arg(s_own)
+        .arg("--txid")
+        .arg(original["txid"].as_str().unwrap())
+        .arg("--fee-rate")
+        .arg("50")
+        .arg("-n")
+        .arg("regtest");
+    let out = cmd.output().unwrap();
+    let replacement: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+
+    assert!(replacement["fees"].as_u64().unwrap() > original["fees"].as_u64().unwrap());
+    assert_ne!(replacement["txid"], original["txid"]);
+    assert_eq!(replacement["address"], original["address"]);
+
+    // the inputs still go to the same destinations, each paying its share of the extra fees
+    let mapping = replacement["mapping"].as_array().unwrap();
+    assert_eq!(mapping.len(), original["mapping"].as_array().unwrap().len());
+    for m in mapping {
+        let o = original["mapping"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|o| o["outpoint"] == m["outpoint"])
+            .unwrap();
+        assert_eq!(m["destination"], o["destination"]);
+        assert!(m["value_out"].as_u64().unwrap() < o["value_out"].as_u64().unwrap());
+    }
+
+    let raw_tx = sign_tx(
+        replacement["psbt"]["base64"].as_str().unwrap(),
+        d_prv,
+        c_prv,
+    )?;
+    let mut nigiri = Command::new(NIGIRI);
+    nigiri
+        .arg("rpc")
+        .arg("testmempoolaccept")
+        .arg(format!(r#"["{}"]"#, raw_tx));
+    let out = nigiri.output().unwrap();
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\"allowed\": true"));
+
+    // TEST CASE: accelerate the same sweep with a child spending one of its outputs
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("cpfp")
+        .arg("-e")
+        .arg(e_own)
+        .arg("-s")
+        .arg(s_own)
+        .arg("--txid")
+        .arg(original["txid"].as_str().unwrap())
+        .arg("--fee-rate")
+        .arg("50")
+        .arg("-n")
+        .arg("regtest");
+    let out = cmd.output().unwrap();
+    let child: Value = serde_json::from_str(&String::from_utf8_lossy(&out.stdout))?;
+
+    let psbt: PartiallySignedTransaction = bdk::bitcoin::consensus::deserialize(
+        &base64::decode(child["psbt"]["base64"].as_str().unwrap()).unwrap(),
+    )
+    .unwrap();
+    let tx = psbt.extract_tx();
+    assert_eq!(tx.input.len(), 1);
+    assert_eq!(
+        tx.input[0].previous_output.txid.to_string(),
+        original["txid"].as_str().unwrap()
+    );
+    // the package of the sweep and the child pays more than the sweep alone
+    assert!(child["fees"].as_u64().unwrap() > original["fees"].as_u64().unwrap());
+
+    let raw_tx = sign_tx(child["psbt"]["base64"].as_str().unwrap(), e_prv, s_prv)?;
+    let mut nigiri = Command::new(NIGIRI);
+    nigiri
+        .arg("rpc")
+        .arg("testmempoolaccept")
+        .arg(format!(r#"["{}"]"#, raw_tx));
+    let out = nigiri.output().unwrap();
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\"allowed\": true"));
+
+    // TEST CASE: a wallet without funds
+    let unused = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/7/*)";
+    let unused_chg = "wpkh([c258d2e4/84h/1h/1h]tpubDDYkZojQFQjht8Tm4jsS3iuEmKjTiEGjG6KnuFNKKJb5A6ZUCUZKdvLdSDWofKi4ToRCwb9poe1XdqfUnP4jaJjCB2Zwv11ZLgSbnZSNecE/8/*)";
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("-d")
+        .arg(unused)
+        .arg("-c")
+        .arg(unused_chg)
+        .arg("-e")
+        .arg(e)
+        .arg("-s")
+        .arg(s)
+        .arg("-n")
+        .arg("regtest");
+    cmd.assert().failure().code(6);
+
+    let mut cmd = Command::cargo_bin(SWEEPTOOL)?;
+    cmd.arg("sweep")
+        .arg("--allow-empty")
+        .arg("-d")
+        .arg(unused)
+        .arg("-c")
+        .arg(unused_chg)
+        .arg("-e")
+        .arg(e)
+        .arg("-s")
+        .arg(s)
+        .arg("-n")
+        .arg("regtest");
+    cmd.assert().success().stdout("{}\n");
+
     Ok(())
 }